@@ -1,4 +1,7 @@
+pub mod bans;
 pub mod dots;
 pub mod players;
+pub mod rooms;
+pub mod viruses;
 pub mod world;
 pub mod ws;
@@ -0,0 +1,232 @@
+use actix::dev::MessageResponse;
+use actix::prelude::*;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use crate::actors::world::KickTarget;
+
+// ********
+// Messages
+// ********
+/// Whether the address of a client is banned
+#[derive(Message)]
+#[rtype(result = "IsBannedResult")]
+pub struct IsBanned(pub Option<IpAddr>);
+
+/// Bans and saves the bans to the file
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct Ban(pub BanTarget);
+
+/// Lifts a ban and saves the bans to the file. Answers whether there was such a ban
+#[derive(Message)]
+#[rtype(result = "Result<bool, String>")]
+pub struct Unban(pub BanTarget);
+
+#[derive(Message)]
+#[rtype(result = "BansList")]
+pub struct GetBans;
+
+// ****************
+// Messages results
+// ****************
+#[derive(MessageResponse, Debug)]
+pub struct IsBannedResult(pub bool);
+
+/// How the bans are written in the file
+#[derive(MessageResponse, Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct BansList {
+    pub ips: HashSet<IpAddr>,
+}
+
+// ********
+// Types
+// ********
+/// Written {"ip": "1.2.3.4"}. Player tokens can't be banned: a client gets a new one by connecting without it
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BanTarget {
+    Ip(IpAddr),
+}
+
+impl From<BanTarget> for KickTarget {
+    fn from(target: BanTarget) -> Self {
+        match target {
+            BanTarget::Ip(ip) => KickTarget::Ip(ip),
+        }
+    }
+}
+
+/// Banned addresses, kept in a file so that they survive restarts
+#[derive(Debug)]
+pub struct Bans {
+    bans: BansList,
+    path: PathBuf,
+}
+
+impl Bans {
+    /// Starts without any ban when the file doesn't exist yet
+    pub fn load(path: PathBuf) -> Result<Self, String> {
+        let bans = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|error| format!("Couldn't parse {}: {}", path.display(), error))?,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => BansList::default(),
+            Err(error) => return Err(format!("Couldn't read {}: {}", path.display(), error)),
+        };
+
+        Ok(Bans { bans, path })
+    }
+
+    // Written next to the file first, so that a crash never leaves it half written
+    fn save(&self, bans: &BansList) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(bans).expect("Couldn't serialize the bans");
+        let temporary_path = self.path.with_extension("tmp");
+
+        fs::write(&temporary_path, content)
+            .and_then(|()| fs::rename(&temporary_path, &self.path))
+            .map_err(|error| format!("Couldn't write {}: {}", self.path.display(), error))
+    }
+}
+
+impl Actor for Bans {
+    type Context = Context<Self>;
+}
+
+// ********
+// Handlers
+// ********
+impl Handler<IsBanned> for Bans {
+    type Result = IsBannedResult;
+
+    fn handle(&mut self, message: IsBanned, _context: &mut Context<Self>) -> Self::Result {
+        IsBannedResult(message.0.is_some_and(|ip| self.bans.ips.contains(&ip)))
+    }
+}
+
+impl Handler<Ban> for Bans {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, message: Ban, _context: &mut Context<Self>) -> Self::Result {
+        info!("Banning {:?}", message.0);
+
+        // Only kept once saved, so that a ban never silently disappears at the next restart
+        let mut bans = self.bans.clone();
+        match message.0 {
+            BanTarget::Ip(ip) => bans.ips.insert(ip),
+        };
+
+        self.save(&bans)?;
+        self.bans = bans;
+        Ok(())
+    }
+}
+
+impl Handler<Unban> for Bans {
+    type Result = Result<bool, String>;
+
+    fn handle(&mut self, message: Unban, _context: &mut Context<Self>) -> Self::Result {
+        let mut bans = self.bans.clone();
+        let removed = match message.0 {
+            BanTarget::Ip(ip) => bans.ips.remove(&ip),
+        };
+
+        if removed {
+            info!("Unbanning {:?}", message.0);
+            self.save(&bans)?;
+            self.bans = bans;
+        }
+
+        Ok(removed)
+    }
+}
+
+impl Handler<GetBans> for Bans {
+    type Result = BansList;
+
+    fn handle(&mut self, _message: GetBans, _context: &mut Context<Self>) -> Self::Result {
+        self.bans.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::Future;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_bans_actor_persist() {
+        let mut system = System::new("bans_persist");
+        let path = std::env::temp_dir().join(format!("bans-{}.json", Uuid::new_v4()));
+        let bans_actor = Bans::load(path.clone()).unwrap().start();
+
+        let (first_ip, second_ip): (IpAddr, IpAddr) = ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+
+        let bans_future = bans_actor
+            .send(Ban(BanTarget::Ip(first_ip)))
+            .join(bans_actor.send(Ban(BanTarget::Ip(second_ip))))
+            .and_then(|(first, second)| {
+                assert_eq!(first, Ok(()));
+                assert_eq!(second, Ok(()));
+
+                bans_actor
+                    .send(IsBanned(Some(first_ip)))
+                    .join(bans_actor.send(IsBanned(Some("10.0.0.3".parse().unwrap()))))
+                    .join(bans_actor.send(IsBanned(None)))
+            })
+            .and_then(|((banned, allowed), unknown)| {
+                assert!(banned.0);
+                assert!(!allowed.0);
+                assert!(!unknown.0);
+
+                bans_actor.send(Unban(BanTarget::Ip(first_ip)))
+            })
+            .map(|result| {
+                assert_eq!(result, Ok(true));
+            });
+
+        system.block_on(bans_future).expect("System error");
+
+        // Another run of the server reads them back
+        let bans = Bans::load(path.clone()).unwrap();
+        assert_eq!(bans.bans.ips, vec![second_ip].into_iter().collect());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_bans_actor_save_failure() {
+        let mut system = System::new("bans_save_failure");
+        let path = std::env::temp_dir()
+            .join(format!("bans-{}", Uuid::new_v4()))
+            .join("bans.json");
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        // The directory of the file doesn't exist
+        let mut bans = Bans::load(path).unwrap();
+        bans.bans.ips.insert(ip);
+        let bans_actor = bans.start();
+
+        let bans_future = bans_actor
+            .send(Ban(BanTarget::Ip("10.0.0.2".parse().unwrap())))
+            .join(bans_actor.send(Unban(BanTarget::Ip(ip))))
+            .and_then(|(banned, unbanned)| {
+                assert!(banned.is_err());
+                assert!(unbanned.is_err());
+
+                bans_actor.send(GetBans)
+            })
+            .map(move |bans| {
+                assert_eq!(bans.ips, vec![ip].into_iter().collect());
+            });
+
+        system.block_on(bans_future).expect("System error");
+    }
+}
@@ -1,81 +1,214 @@
 use actix::dev::MessageResponse;
 use actix::prelude::*;
+use serde::Serialize;
 use uuid::Uuid;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 
-use crate::actors::world::Coordinates;
-use crate::consts::{DELTA_VIEWPORT, DOTS_CREATE_INTERVAL, DOT_SIZE, MAX_DOTS_AMOUNT};
-use crate::utils::{generate_dots};
+use crate::actors::world::{Coordinates, Ping, Shutdown, Velocity};
+use crate::config::Config;
+use crate::consts::{DELTA_VIEWPORT, DOTS_GRID_BUCKET_SIZE, DOT_MASS, DOT_SIZE, EJECT_DECELERATION};
+use crate::grid::Grid;
+use crate::metrics;
+use crate::utils::{apply_delta, generate_dots};
 
 // ********
 // Messages
 // ********
+/// The changes in the dots of the viewport since the last time the player asked.
+/// With full, every dot of the viewport is returned as if the player never asked before
 #[derive(Message)]
 #[rtype(result = "GetDotsResult")]
 pub struct GetDots {
     pub id: Uuid,
     pub coordinates: Coordinates,
     pub viewport_size: Coordinates,
+    pub full: bool,
 }
 
+/// Only the dots that exist, are not moving anymore and overlap one of the areas are deleted.
+/// Areas are squares given by their top left corner and their size, like the reach of the cells of a player
 #[derive(Message)]
-pub struct DeleteDots(pub Vec<Uuid>);
+#[rtype(result = "DeleteDotsResult")]
+pub struct DeleteDots {
+    pub ids: Vec<Uuid>,
+    pub areas: Vec<(Coordinates, u32)>,
+}
+
+/// Adds dots to the world, like the ones ejected by players
+#[derive(Message)]
+pub struct AddDots(pub Vec<Dot>);
+
+/// Forgets the dots known by a player that left
+#[derive(Message)]
+pub struct ForgetPlayer(pub Uuid);
 
 // ****************
 // Messages results
 // ****************
 #[derive(MessageResponse, Message, Debug)]
 pub struct GetDotsResult {
-    pub dots: HashMap<Uuid, Coordinates>,
+    pub dots_added: HashMap<Uuid, DotData>,
+    pub dots_removed: Vec<Uuid>,
     pub player_id: Uuid,
 }
 
+/// How many of the requested dots were deleted, their total mass,
+/// and for every player that knew some of them, the ones it has to forget
+#[derive(MessageResponse, Debug)]
+pub struct DeleteDotsResult {
+    pub count: u32,
+    pub mass: u32,
+    pub removed: HashMap<Uuid, Vec<Uuid>>,
+}
+
 // ********
 // Types
 // ********
+#[derive(Debug, Copy, Clone)]
+pub struct Dot {
+    pub coordinates: Coordinates,
+    pub mass: u32,
+    // Ejected dots travel until they settle. They can only be consumed once settled
+    pub velocity: Velocity,
+}
+
+impl Dot {
+    pub fn new(coordinates: Coordinates) -> Self {
+        Dot {
+            coordinates,
+            mass: DOT_MASS,
+            velocity: Velocity::default(),
+        }
+    }
+
+    fn overlaps(&self, (coordinates, size): (Coordinates, u32)) -> bool {
+        self.coordinates.x < coordinates.x.saturating_add(size)
+            && self.coordinates.x + DOT_SIZE > coordinates.x
+            && self.coordinates.y < coordinates.y.saturating_add(size)
+            && self.coordinates.y + DOT_SIZE > coordinates.y
+    }
+}
+
+/// A dot as seen from the viewport of a player
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct DotData {
+    pub coordinates: Coordinates,
+    pub mass: u32,
+}
+
 #[derive(MessageResponse, Clone, Debug)]
 pub struct Dots {
-    pub dots: HashMap<Uuid, Coordinates>,
+    pub dots: HashMap<Uuid, Dot>,
+    // Dots that still have a velocity
+    pub moving_dots: Vec<Uuid>,
+    // Index of the dots by the region they are in, kept in sync with dots
+    pub grid: Grid<Uuid>,
+    // The dots each player was told about
+    pub known_dots: HashMap<Uuid, HashSet<Uuid>>,
+    // Known dots that settled since, sent again or removed with the next update of each player
+    pub settled_dots: HashMap<Uuid, HashSet<Uuid>>,
     pub dots_count: u32,
     pub max_dots_amount: u32,
+    world_size: Coordinates,
+    create_interval: Duration,
+    move_interval: Duration,
 }
 
 impl Dots {
+    fn insert_dot(&mut self, id: Uuid, dot: Dot) {
+        if self.dots.insert(id, dot).is_none() {
+            metrics::DOTS.inc();
+        }
+        self.grid.insert(id, dot.coordinates, DOT_SIZE);
+        self.dots_count = self.dots.len() as u32;
+    }
+
+    fn remove_dot(&mut self, id: &Uuid) -> Option<Dot> {
+        let dot = self.dots.remove(id)?;
+
+        metrics::DOTS.dec();
+        self.grid.remove(*id);
+        self.dots_count = self.dots.len() as u32;
+        Some(dot)
+    }
+
+    fn generate_dots(&mut self) {
+        for (id, dot) in generate_dots(self.max_dots_amount.saturating_sub(self.dots_count), self.world_size) {
+            self.insert_dot(id, dot);
+        }
+    }
+
     fn run_dots_creation_interval(&self, context: &mut Context<Self>) {
-        context.run_interval(DOTS_CREATE_INTERVAL, |actor, _context| {
+        context.run_interval(self.create_interval, |actor, _context| {
             if actor.dots_count < actor.max_dots_amount {
-                generate_dots(&mut actor.dots, actor.max_dots_amount);
-                actor.dots_count = actor.dots.len() as u32;
+                actor.generate_dots();
             }
         });
     }
 
-    fn find_viewport_dots(&self, viewport_size: Coordinates, player: Coordinates) -> HashMap<Uuid, Coordinates> {
-        let min_x = (player.x)
-            .checked_sub((viewport_size.x / 2) - DELTA_VIEWPORT)
-            .unwrap_or(0);
-        let max_x = player.x + (viewport_size.x / 2) + DELTA_VIEWPORT;
-        let min_y = (player.y)
-            .checked_sub((viewport_size.y / 2) - DELTA_VIEWPORT)
-            .unwrap_or(0);
-        let max_y = player.y + (viewport_size.y / 2) + DELTA_VIEWPORT;
+    fn run_dots_move_interval(&self, context: &mut Context<Self>) {
+        context.run_interval(self.move_interval, |actor, _context| {
+            let (dots, grid) = (&mut actor.dots, &mut actor.grid);
+            let (known_dots, settled_dots) = (&actor.known_dots, &mut actor.settled_dots);
+            let (world_size, move_interval) = (actor.world_size, actor.move_interval);
+
+            actor.moving_dots.retain(|id| match dots.get_mut(id) {
+                Some(dot) => {
+                    let (moved_x, moved_y) = dot.velocity.glide(move_interval, EJECT_DECELERATION);
+                    let (x, _clamped) = apply_delta(dot.coordinates.x, moved_x, world_size.x - DOT_SIZE);
+                    let (y, _clamped) = apply_delta(dot.coordinates.y, moved_y, world_size.y - DOT_SIZE);
+
+                    dot.coordinates = Coordinates { x, y };
+                    grid.insert(*id, dot.coordinates, DOT_SIZE);
+
+                    // Once settled, the dot is sent again to the players that saw it moving, with its final position,
+                    // or removed for the ones it went out of sight of
+                    let settled = dot.velocity.is_null();
+                    if settled {
+                        for (player_id, known) in known_dots.iter() {
+                            if known.contains(id) {
+                                settled_dots.entry(*player_id).or_default().insert(*id);
+                            }
+                        }
+                    }
+                    !settled
+                }
+                None => false,
+            });
+        });
+    }
 
-        let dots_in_viewport: HashMap<Uuid, Coordinates> = self
-            .dots
-            .iter()
-            .filter(|(_id, coordinates)| {
-                coordinates.x >= min_x
-                    && (coordinates.x + DOT_SIZE < max_x)
-                    && coordinates.y >= min_y
-                    && coordinates.y + DOT_SIZE < max_y
+    fn find_viewport_dots(&self, viewport_size: Coordinates, player: Coordinates) -> HashMap<Uuid, DotData> {
+        let min_x = (player.x).saturating_sub((viewport_size.x / 2).saturating_sub(DELTA_VIEWPORT));
+        let max_x = player
+            .x
+            .saturating_add(viewport_size.x / 2)
+            .saturating_add(DELTA_VIEWPORT);
+        let min_y = (player.y).saturating_sub((viewport_size.y / 2).saturating_sub(DELTA_VIEWPORT));
+        let max_y = player
+            .y
+            .saturating_add(viewport_size.y / 2)
+            .saturating_add(DELTA_VIEWPORT);
+
+        let dots_in_viewport: HashMap<Uuid, DotData> = self
+            .grid
+            .query(Coordinates { x: min_x, y: min_y }, Coordinates { x: max_x, y: max_y })
+            .into_iter()
+            .filter_map(|id| self.dots.get(&id).map(|dot| (id, dot)))
+            .filter(|(_id, dot)| {
+                dot.coordinates.x >= min_x
+                    && (dot.coordinates.x + DOT_SIZE < max_x)
+                    && dot.coordinates.y >= min_y
+                    && dot.coordinates.y + DOT_SIZE < max_y
             })
-            .map(|(id, coordinates)| {
+            .map(|(id, dot)| {
                 (
-                    *id,
-                    Coordinates {
-                        x: coordinates.x - min_x,
-                        y: coordinates.y - min_y,
+                    id,
+                    DotData {
+                        coordinates: dot.coordinates,
+                        mass: dot.mass,
                     },
                 )
             })
@@ -85,36 +218,112 @@ impl Dots {
     }
 }
 
-impl Default for Dots {
-    fn default() -> Self {
+impl Dots {
+    pub fn from_config(config: &Config) -> Self {
         Dots {
             dots: HashMap::new(),
+            moving_dots: Vec::new(),
+            grid: Grid::new(DOTS_GRID_BUCKET_SIZE, config.world_size),
+            known_dots: HashMap::new(),
+            settled_dots: HashMap::new(),
             dots_count: 0,
-            max_dots_amount: MAX_DOTS_AMOUNT,
+            max_dots_amount: config.max_dots_amount,
+            world_size: config.world_size,
+            create_interval: config.intervals.dots_create,
+            move_interval: config.intervals.dots_move,
         }
     }
 }
 
+impl Default for Dots {
+    fn default() -> Self {
+        Dots::from_config(&Config::default())
+    }
+}
+
 impl Handler<GetDots> for Dots {
     type Result = GetDotsResult;
 
     fn handle(&mut self, message: GetDots, _context: &mut Context<Self>) -> Self::Result {
-        let dots = self.find_viewport_dots(message.viewport_size, message.coordinates);
+        let visible = self.find_viewport_dots(message.viewport_size, message.coordinates);
+        let known = self.known_dots.entry(message.id).or_default();
+        let settled = self.settled_dots.remove(&message.id).unwrap_or_default();
+
+        if message.full {
+            known.clear();
+        }
+
+        let dots_removed = known.iter().filter(|id| !visible.contains_key(id)).copied().collect();
+        let dots_added = visible
+            .iter()
+            .filter(|(id, _dot)| !known.contains(id) || settled.contains(id))
+            .map(|(id, dot)| (*id, *dot))
+            .collect();
+        *known = visible.keys().copied().collect();
 
         GetDotsResult {
-            dots,
+            dots_added,
+            dots_removed,
             player_id: message.id,
         }
     }
 }
 
 impl Handler<DeleteDots> for Dots {
+    type Result = DeleteDotsResult;
+
+    fn handle(&mut self, message: DeleteDots, _context: &mut Context<Self>) -> Self::Result {
+        let mut result = DeleteDotsResult {
+            count: 0,
+            mass: 0,
+            removed: HashMap::new(),
+        };
+        let DeleteDots { ids, areas } = message;
+
+        for id in ids {
+            let consumable = self
+                .dots
+                .get(&id)
+                .is_some_and(|dot| dot.velocity.is_null() && areas.iter().any(|area| dot.overlaps(*area)));
+
+            if consumable {
+                let dot = self.remove_dot(&id).unwrap();
+
+                result.count += 1;
+                result.mass += dot.mass;
+
+                for (player_id, known) in self.known_dots.iter_mut() {
+                    if known.remove(&id) {
+                        result.removed.entry(*player_id).or_default().push(id);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+}
+
+impl Handler<ForgetPlayer> for Dots {
     type Result = ();
 
-    fn handle(&mut self, message: DeleteDots, _context: &mut Context<Self>) {
-        for id in message.0 {
-            self.dots.remove(&id);
-            self.dots_count -= 1;
+    fn handle(&mut self, message: ForgetPlayer, _context: &mut Context<Self>) {
+        self.known_dots.remove(&message.0);
+        self.settled_dots.remove(&message.0);
+    }
+}
+
+impl Handler<AddDots> for Dots {
+    type Result = ();
+
+    fn handle(&mut self, message: AddDots, _context: &mut Context<Self>) {
+        for dot in message.0 {
+            let id = Uuid::new_v4();
+
+            if !dot.velocity.is_null() {
+                self.moving_dots.push(id);
+            }
+            self.insert_dot(id, dot);
         }
     }
 }
@@ -123,16 +332,39 @@ impl Actor for Dots {
     type Context = Context<Self>;
 
     fn started(&mut self, context: &mut Context<Self>) {
-        generate_dots(&mut self.dots, self.max_dots_amount);
-        self.dots_count = self.dots.len() as u32;
+        self.generate_dots();
         self.run_dots_creation_interval(context);
+        self.run_dots_move_interval(context);
+    }
+
+    fn stopped(&mut self, _context: &mut Context<Self>) {
+        metrics::DOTS.sub(self.dots.len() as i64);
+    }
+}
+
+impl Handler<Shutdown> for Dots {
+    type Result = ();
+
+    fn handle(&mut self, _message: Shutdown, context: &mut Context<Self>) {
+        context.stop();
+    }
+}
+
+impl Handler<Ping> for Dots {
+    type Result = Result<(), MailboxError>;
+
+    fn handle(&mut self, _message: Ping, _context: &mut Context<Self>) -> Self::Result {
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use futures::{Future};
+    use crate::config::Intervals;
+    use crate::consts::{MAX_DOTS_AMOUNT, WORLD_X_SIZE, WORLD_Y_SIZE};
+    use futures::{future, Future};
+    use std::cmp;
     use std::sync::Arc;
 
     #[derive(Message)]
@@ -143,13 +375,7 @@ mod tests {
         type Result = Dots;
 
         fn handle(&mut self, _message: GetState, _context: &mut Context<Self>) -> Dots {
-            let dots = self.dots.clone();
-
-            Dots {
-                dots,
-                dots_count: self.dots_count,
-                max_dots_amount: self.max_dots_amount,
-            }
+            self.clone()
         }
     }
 
@@ -169,6 +395,7 @@ mod tests {
                     id: Uuid::parse_str("78a40100-4dc3-46e4-8a91-00e0316586e4").unwrap(),
                     coordinates: Coordinates { x: 0, y: 0 },
                     viewport_size: Coordinates { x: 1000, y: 1000 },
+                    full: true,
                 })
             })
             .and_then(|result: GetDotsResult| {
@@ -180,28 +407,30 @@ mod tests {
                     "77d40cd1-be99-44d2-9bcf-7450f736fdba",
                 ];
 
-                assert_eq!(result.dots.len(), 5);
+                assert_eq!(result.dots_added.len(), 5);
                 for id in dots_id {
-                    assert_eq!(result.dots.contains_key(&Uuid::parse_str(id).unwrap()), true);
+                    assert!(result.dots_added.contains_key(&Uuid::parse_str(id).unwrap()));
                 }
                 dots_actor.send(GetDots {
                     id: Uuid::parse_str("78a40100-4dc3-46e4-8a91-00e0316586e4").unwrap(),
                     coordinates: Coordinates { x: 1000, y: 1000 },
                     viewport_size: Coordinates { x: 1000, y: 1000 },
+                    full: true,
                 })
             })
             .and_then(|result: GetDotsResult| {
                 let dots_id = vec!["9bea8e0c-5d0a-4018-be7d-2ae9af088a0c"];
 
-                assert_eq!(result.dots.len(), 1);
+                assert_eq!(result.dots_added.len(), 1);
                 for id in dots_id {
-                    assert_eq!(result.dots.contains_key(&Uuid::parse_str(id).unwrap()), true);
+                    assert!(result.dots_added.contains_key(&Uuid::parse_str(id).unwrap()));
                 }
 
                 dots_actor.send(GetDots {
                     id: Uuid::parse_str("78a40100-4dc3-46e4-8a91-00e0316586e4").unwrap(),
                     coordinates: Coordinates { x: 0, y: 600 },
                     viewport_size: Coordinates { x: 1000, y: 1000 },
+                    full: true,
                 })
             })
             .map(|result: GetDotsResult| {
@@ -212,15 +441,43 @@ mod tests {
                     "ffe016bf-a99e-470f-aaab-1c5f1eb1c04b",
                 ];
 
-                assert_eq!(result.dots.len(), 4);
+                assert_eq!(result.dots_added.len(), 4);
                 for id in dots_id {
-                    assert_eq!(result.dots.contains_key(&Uuid::parse_str(id).unwrap()), true);
+                    assert!(result.dots_added.contains_key(&Uuid::parse_str(id).unwrap()));
                 }
             });
 
         system.block_on(get_dots_future).expect("System error");
     }
 
+    // An area covering every dot
+    fn whole_world() -> Vec<(Coordinates, u32)> {
+        vec![(Coordinates { x: 0, y: 0 }, cmp::max(WORLD_X_SIZE, WORLD_Y_SIZE))]
+    }
+
+    #[test]
+    fn test_dots_actor_huge_viewport() {
+        let mut system = System::new("dots_huge_viewport");
+        let dots_actor = Dots::default().start();
+
+        // Clients can't ask for such a viewport, but the dots actor doesn't overflow nor hang on it
+        let get_dots_future = dots_actor
+            .send(GetDots {
+                id: Uuid::parse_str("78a40100-4dc3-46e4-8a91-00e0316586e4").unwrap(),
+                coordinates: Coordinates { x: 1000, y: 1000 },
+                viewport_size: Coordinates {
+                    x: u32::MAX,
+                    y: u32::MAX,
+                },
+                full: true,
+            })
+            .map(|result: GetDotsResult| {
+                assert_eq!(result.dots_added.len(), 12);
+            });
+
+        system.block_on(get_dots_future).expect("System error");
+    }
+
     #[test]
     fn test_dots_actor_delete_dots() {
         let mut system = System::new("dots_deletion");
@@ -233,20 +490,280 @@ mod tests {
                 assert_eq!(result.dots_count, 12);
                 assert_eq!(result.max_dots_amount, MAX_DOTS_AMOUNT);
 
-                dots_actor.do_send(DeleteDots(vec![
-                    Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").unwrap(),
-                    Uuid::parse_str("e0183a5f-92af-4379-8d8d-cfd729d77d59").unwrap(),
-                    Uuid::parse_str("20066e7c-5dec-434f-97d1-663de407b05e").unwrap(),
-                    Uuid::parse_str("a0e3c51b-23a5-4809-b635-3eb6b3b1f794").unwrap(),
-                    Uuid::parse_str("77d40cd1-be99-44d2-9bcf-7450f736fdba").unwrap(),
-                ]));
+                dots_actor.do_send(DeleteDots {
+                    ids: vec![
+                        Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").unwrap(),
+                        Uuid::parse_str("e0183a5f-92af-4379-8d8d-cfd729d77d59").unwrap(),
+                        Uuid::parse_str("20066e7c-5dec-434f-97d1-663de407b05e").unwrap(),
+                        Uuid::parse_str("a0e3c51b-23a5-4809-b635-3eb6b3b1f794").unwrap(),
+                        Uuid::parse_str("77d40cd1-be99-44d2-9bcf-7450f736fdba").unwrap(),
+                    ],
+                    areas: whole_world(),
+                });
                 dots_actor.send(GetState)
             })
             .map(|result: Dots| {
+                let world_size = Coordinates {
+                    x: WORLD_X_SIZE,
+                    y: WORLD_Y_SIZE,
+                };
+
                 assert_eq!(result.dots.len(), 7);
                 assert_eq!(result.dots_count, 7);
+                assert_eq!(result.grid.query(Coordinates { x: 0, y: 0 }, world_size).len(), 7);
             });
 
         system.block_on(delete_dots_future).expect("System error");
     }
+
+    #[test]
+    fn test_dots_actor_delete_dots_out_of_reach() {
+        let mut system = System::new("dots_reach");
+        let dots_actor = Arc::new(Dots::default().start());
+        let ids = vec![
+            Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").unwrap(),
+            Uuid::parse_str("e0183a5f-92af-4379-8d8d-cfd729d77d59").unwrap(),
+            Uuid::parse_str("be196b9b-6a85-4ba3-b7ac-c1dd02d6178a").unwrap(),
+        ];
+
+        let reach_future = dots_actor
+            .send(DeleteDots {
+                ids: ids.clone(),
+                areas: Vec::new(),
+            })
+            .and_then(|result: DeleteDotsResult| {
+                // A player without cells can't eat anything
+                assert_eq!(result.count, 0);
+
+                // Only the dot at (0, 0) is within reach, the others are far away
+                dots_actor.send(DeleteDots {
+                    ids,
+                    areas: vec![(Coordinates { x: 0, y: 0 }, 50)],
+                })
+            })
+            .and_then(|result: DeleteDotsResult| {
+                assert_eq!(result.count, 1);
+                assert_eq!(result.mass, DOT_MASS);
+
+                dots_actor.send(GetState)
+            })
+            .map(|result: Dots| {
+                assert_eq!(result.dots.len(), 11);
+                assert!(result
+                    .dots
+                    .contains_key(&Uuid::parse_str("be196b9b-6a85-4ba3-b7ac-c1dd02d6178a").unwrap()));
+            });
+
+        system.block_on(reach_future).expect("System error");
+    }
+
+    #[test]
+    fn test_dots_actor_add_dots() {
+        let mut system = System::new("dots_adding");
+        let dots_actor = Arc::new(Dots::default().start());
+
+        dots_actor.do_send(AddDots(vec![
+            Dot {
+                coordinates: Coordinates { x: 500, y: 500 },
+                mass: 4,
+                velocity: Velocity { x: 1000.0, y: 0.0 },
+            },
+            Dot {
+                coordinates: Coordinates { x: 600, y: 600 },
+                mass: 4,
+                velocity: Velocity::default(),
+            },
+        ]));
+
+        let add_dots_future = dots_actor
+            .send(GetState)
+            .and_then(|result: Dots| {
+                assert_eq!(result.dots.len(), 14);
+                assert_eq!(result.dots_count, 14);
+                assert_eq!(result.moving_dots.len(), 1);
+
+                let added_dots = result
+                    .dots
+                    .iter()
+                    .filter(|(_id, dot)| dot.mass == 4)
+                    .map(|(id, _dot)| *id)
+                    .collect();
+
+                dots_actor.send(DeleteDots {
+                    ids: added_dots,
+                    areas: whole_world(),
+                })
+            })
+            .and_then(|result: DeleteDotsResult| {
+                // The moving dot can't be consumed yet
+                assert_eq!(result.count, 1);
+                assert_eq!(result.mass, 4);
+
+                dots_actor.send(DeleteDots {
+                    ids: vec![
+                        Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").unwrap(),
+                        Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").unwrap(),
+                    ],
+                    areas: whole_world(),
+                })
+            })
+            .and_then(|result: DeleteDotsResult| {
+                assert_eq!(result.count, 1);
+                assert_eq!(result.mass, DOT_MASS);
+
+                dots_actor.send(GetState)
+            })
+            .map(|result: Dots| {
+                assert_eq!(result.dots.len(), 12);
+                assert_eq!(result.dots_count, 12);
+            });
+
+        system.block_on(add_dots_future).expect("System error");
+    }
+
+    #[test]
+    fn test_dots_actor_delta_updates() {
+        let mut system = System::new("dots_delta");
+        let dots_actor = Arc::new(Dots::default().start());
+        let player_id = Uuid::parse_str("78a40100-4dc3-46e4-8a91-00e0316586e4").unwrap();
+        let other_player_id = Uuid::parse_str("1ff42309-6266-470a-9e4d-09babbc715f3").unwrap();
+        let get_dots = move |coordinates, full| GetDots {
+            id: player_id,
+            coordinates,
+            viewport_size: Coordinates { x: 1000, y: 1000 },
+            full,
+        };
+
+        let delta_future = dots_actor
+            .send(get_dots(Coordinates { x: 0, y: 0 }, true))
+            .and_then(|result: GetDotsResult| {
+                assert_eq!(result.dots_added.len(), 5);
+                assert!(result.dots_removed.is_empty());
+
+                dots_actor.send(get_dots(Coordinates { x: 0, y: 0 }, false))
+            })
+            .and_then(|result: GetDotsResult| {
+                // Nothing changed since the last time
+                assert!(result.dots_added.is_empty());
+                assert!(result.dots_removed.is_empty());
+
+                dots_actor.send(get_dots(Coordinates { x: 0, y: 600 }, false))
+            })
+            .and_then(|result: GetDotsResult| {
+                let dots_removed: HashSet<Uuid> = result.dots_removed.into_iter().collect();
+                let expected_removed: HashSet<Uuid> = vec![
+                    "f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4",
+                    "e0183a5f-92af-4379-8d8d-cfd729d77d59",
+                    "20066e7c-5dec-434f-97d1-663de407b05e",
+                    "a0e3c51b-23a5-4809-b635-3eb6b3b1f794",
+                ]
+                .into_iter()
+                .map(|id| Uuid::parse_str(id).unwrap())
+                .collect();
+
+                // The dot at (0, 200) stays in the viewport
+                assert_eq!(result.dots_added.len(), 3);
+                assert_eq!(dots_removed, expected_removed);
+
+                dots_actor.send(DeleteDots {
+                    ids: vec![
+                        Uuid::parse_str("be196b9b-6a85-4ba3-b7ac-c1dd02d6178a").unwrap(),
+                        Uuid::parse_str("1f4c367c-f35f-4eda-8cb1-c4494fb542ab").unwrap(),
+                    ],
+                    areas: whole_world(),
+                })
+            })
+            .and_then(|result: DeleteDotsResult| {
+                // Only the player that knew about the first dot has to forget it
+                assert_eq!(result.count, 2);
+                assert_eq!(result.removed.len(), 1);
+                assert_eq!(
+                    result.removed.get(&player_id),
+                    Some(&vec![Uuid::parse_str("be196b9b-6a85-4ba3-b7ac-c1dd02d6178a").unwrap()])
+                );
+                assert!(!result.removed.contains_key(&other_player_id));
+
+                dots_actor.send(get_dots(Coordinates { x: 0, y: 600 }, false))
+            })
+            .map(|result: GetDotsResult| {
+                assert!(result.dots_added.is_empty());
+                assert!(result.dots_removed.is_empty());
+            });
+
+        system.block_on(delta_future).expect("System error");
+    }
+
+    #[test]
+    fn test_dots_actor_settled_dots() {
+        let mut system = System::new("dots_settled");
+        let config = Config {
+            intervals: Intervals {
+                dots_move: Duration::from_millis(500),
+                ..Intervals::default()
+            },
+            ..Config::default()
+        };
+        let dots_actor = Arc::new(Dots::from_config(&config).start());
+        let player_id = Uuid::parse_str("78a40100-4dc3-46e4-8a91-00e0316586e4").unwrap();
+        let get_dots = move |full| GetDots {
+            id: player_id,
+            coordinates: Coordinates { x: 500, y: 500 },
+            viewport_size: Coordinates { x: 1000, y: 1000 },
+            full,
+        };
+
+        // Both settle after a single move, the first one out of the viewport
+        dots_actor.do_send(AddDots(vec![
+            Dot {
+                coordinates: Coordinates { x: 1000, y: 500 },
+                mass: 4,
+                velocity: Velocity { x: 1000.0, y: 0.0 },
+            },
+            Dot {
+                coordinates: Coordinates { x: 200, y: 600 },
+                mass: 4,
+                velocity: Velocity { x: 200.0, y: 0.0 },
+            },
+        ]));
+
+        let settled_future = dots_actor
+            .send(get_dots(true))
+            .and_then(|result: GetDotsResult| {
+                let ejected: Vec<Uuid> = result
+                    .dots_added
+                    .iter()
+                    .filter(|(_id, dot)| dot.mass == 4)
+                    .map(|(id, _dot)| *id)
+                    .collect();
+                assert_eq!(ejected.len(), 2);
+
+                future::loop_fn((), |()| {
+                    dots_actor.send(GetState).map(|result: Dots| {
+                        if result.moving_dots.is_empty() {
+                            future::Loop::Break(result)
+                        } else {
+                            future::Loop::Continue(())
+                        }
+                    })
+                })
+                .and_then(|state| dots_actor.send(get_dots(false)).map(|result| (state, result)))
+            })
+            .map(|(state, result): (Dots, GetDotsResult)| {
+                let gone = state
+                    .dots
+                    .iter()
+                    .find(|(_id, dot)| dot.mass == 4 && dot.coordinates.x == 1500)
+                    .map(|(id, _dot)| *id)
+                    .expect("The first dot didn't move");
+
+                assert_eq!(result.dots_removed, vec![gone]);
+                assert_eq!(result.dots_added.len(), 1);
+                assert!(result
+                    .dots_added
+                    .values()
+                    .all(|dot| dot.coordinates == Coordinates { x: 300, y: 600 }));
+            });
+
+        system.block_on(settled_future).expect("System error");
+    }
 }
@@ -1,75 +1,530 @@
 use actix::dev::MessageResponse;
 use actix::prelude::*;
+use serde::Serialize;
 use uuid::Uuid;
 
+use std::cmp;
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
-use crate::actors::world::Coordinates;
-use crate::consts::DEFAULT_PLAYER_SIZE;
-use crate::utils::generate_coordinates;
+use crate::actors::dots::Dot;
+use crate::actors::world::{Coordinates, Ping, Shutdown, Velocity};
+use crate::client_messages::Delta;
+use crate::config::Config;
+use crate::consts::{
+    DEFAULT_PLAYER_SIZE, DOT_SIZE, EJECT_MASS, EJECT_SPEED, LEADERBOARD_SIZE, MAX_CELLS_AMOUNT, MAX_MOVE_INTERVAL,
+    MERGE_COOLDOWN, MIN_EJECT_SIZE, MIN_SPLIT_SIZE, MOVE_TOLERANCE, PLAYERS_GRID_BUCKET_SIZE, PLAYER_BASE_SPEED,
+    PLAYER_MIN_SPEED, SPLIT_DECELERATION, SPLIT_SPEED,
+};
+use crate::grid::Grid;
+use crate::metrics;
+use crate::utils::{apply_delta, generate_coordinates};
 
 // ********
 // Messages
 // ********
+/// Creates a new player, or reattaches to the alive player with the given token if there is one
 #[derive(Message)]
 #[rtype(result = "CreatePlayerResult")]
-pub struct CreatePlayer(pub Coordinates);
+pub struct CreatePlayer {
+    pub viewport_size: Coordinates,
+    pub token: Option<Uuid>,
+    pub profile: Profile,
+}
 
 #[derive(Message)]
 #[rtype(result = "MovePlayerResult")]
 pub struct MovePlayer {
     pub id: Uuid,
-    pub moved: Coordinates,
+    pub moved: Delta,
     pub size: u32,
+    // Amount of dots the player actually ate since its last move, and the mass they were worth
+    pub dots_consumed: u32,
+    pub dots_mass: u32,
+}
+
+#[derive(Message)]
+#[rtype(result = "SplitPlayerResult")]
+pub struct SplitPlayer {
+    pub id: Uuid,
+    pub direction: Delta,
+}
+
+/// Makes a cell that hit a virus absorb its mass and burst into as many cells as the player can have
+#[derive(Message)]
+#[rtype(result = "SplitPlayerResult")]
+pub struct PopCell {
+    pub id: Uuid,
+    pub cell: u32,
+    pub mass: u32,
+}
+
+/// Shoots EJECT_MASS out of every cell big enough, towards direction
+#[derive(Message)]
+#[rtype(result = "EjectMassResult")]
+pub struct EjectMass {
+    pub id: Uuid,
+    pub direction: Delta,
 }
 
 #[derive(Debug, Message)]
-#[rtype(result = "Player")]
+#[rtype(result = "GetPlayerResult")]
 pub struct GetPlayer(pub Uuid);
 
+/// Where the cells of an alive player could have been since its last move, the only places it can eat dots in
+#[derive(Debug, Message)]
+#[rtype(result = "GetReachResult")]
+pub struct GetReach(pub Uuid);
+
+#[derive(Debug, Message)]
+#[rtype(result = "ConsumeCellResult")]
+pub struct ConsumeCell(pub CollisionData);
+
 #[derive(Debug, Message)]
 #[rtype(result = "GetPlayersInViewportResult")]
 pub struct GetPlayersInViewport(pub Uuid);
 
+#[derive(Debug, Message)]
+#[rtype(result = "GetProfileResult")]
+pub struct GetProfile(pub Uuid);
+
+/// The players within radius of the player, not including itself. The ones that aren't alive are placed where
+/// their viewport is, e.g. on the player they spectate
+#[derive(Debug, Message)]
+#[rtype(result = "GetNearbyPlayersResult")]
+pub struct GetNearbyPlayers {
+    pub id: Uuid,
+    pub radius: u32,
+}
+
+/// Ranks the alive players by mass
+#[derive(Debug, Message)]
+#[rtype(result = "GetLeaderboardResult")]
+pub struct GetLeaderboard;
+
+/// Where the players with the given ids are and how big they are. Unknown ids are left out
+#[derive(Debug, Message)]
+#[rtype(result = "GetPlayersSummariesResult")]
+pub struct GetPlayersSummaries(pub Vec<Uuid>);
+
+/// Makes a dead player follow the player with the given id
+#[derive(Debug, Message)]
+pub struct SpectatePlayer {
+    pub id: Uuid,
+    pub target: Uuid,
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "RemovePlayerResult")]
+pub struct RemovePlayer(pub Uuid);
+
+/// Removes the player at the end of its grace period, unless a client took it back since it disconnected.
+/// None when it was taken back
+#[derive(Debug, Message)]
+#[rtype(result = "Option<RemovePlayerResult>")]
+pub struct RemoveDisconnectedPlayer {
+    pub id: Uuid,
+    pub disconnected_at: Instant,
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "RespawnPlayerResult")]
+pub struct RespawnPlayer {
+    pub id: Uuid,
+    pub viewport_size: Coordinates,
+}
+
 // ****************
 // Messages results
 // ****************
-#[derive(MessageResponse, Copy, Clone)]
+#[derive(MessageResponse, Copy, Clone, Debug)]
 pub struct CreatePlayerResult {
     pub id: Uuid,
     pub coordinates: Coordinates,
+    pub token: Uuid,
+}
+
+#[derive(MessageResponse)]
+pub struct MovePlayerResult {
+    pub collisions: Vec<CollisionData>,
+    pub correction: Option<Correction>,
+    // The cells of the player once moved
+    pub cells: Vec<CellData>,
 }
 
+/// The new cells of the player. None when none of its cells could split
+#[derive(MessageResponse)]
+pub struct SplitPlayerResult(pub Option<Correction>);
+
+/// The new cells of the player and the pellets it ejected. No correction when none of its cells could eject
 #[derive(MessageResponse)]
-pub struct MovePlayerResult(pub Option<CollisionData>);
+pub struct EjectMassResult {
+    pub correction: Option<Correction>,
+    pub ejected: Vec<Dot>,
+}
+
+/// None when the player was removed, e.g. at the end of its grace period while its client reconnected
+#[derive(MessageResponse, Debug)]
+pub struct GetPlayerResult(pub Option<Viewport>);
+
+/// A square for every cell, given by its top left corner and its size. Empty when the player isn't alive
+#[derive(MessageResponse, Debug)]
+pub struct GetReachResult(pub Vec<(Coordinates, u32)>);
+
+#[derive(MessageResponse, Debug)]
+pub enum ConsumeCellResult {
+    // One of the cells doesn't exist anymore or one of the players isn't alive
+    NotApplied,
+    Consumed {
+        winner_size: u32,
+        loser_cells: Vec<CellData>,
+    },
+    // The consumed cell was the last cell of the losing player
+    Died {
+        winner_size: u32,
+        final_size: u32,
+        stats: PlayerStats,
+    },
+}
+
+/// The players that had the removed player in their viewport
+#[derive(MessageResponse, Debug)]
+pub struct RemovePlayerResult {
+    pub nearby: Vec<Uuid>,
+}
+
+/// None when the player doesn't exist or is still alive
+#[derive(MessageResponse, Debug)]
+pub struct RespawnPlayerResult(pub Option<CreatePlayerResult>);
+
+#[derive(MessageResponse, Debug)]
+pub struct GetProfileResult(pub Option<Profile>);
+
+#[derive(MessageResponse, Debug)]
+pub struct GetNearbyPlayersResult(pub Vec<Uuid>);
+
+/// The LEADERBOARD_SIZE biggest players, and the rank of every alive player starting from 1
+#[derive(MessageResponse, Debug)]
+pub struct GetLeaderboardResult {
+    pub leaders: Vec<LeaderboardEntry>,
+    pub ranks: HashMap<Uuid, u32>,
+}
 
 #[derive(MessageResponse, Debug)]
-pub struct GetPlayersInViewportResult(Vec<(Coordinates, u32)>);
+pub struct GetPlayersSummariesResult(pub Vec<PlayerSummary>);
+
+#[derive(MessageResponse, Message, Debug)]
+pub struct GetPlayersInViewportResult {
+    pub players: Vec<PlayerInViewport>,
+}
 
 // ********
 // Types
 // ********
-#[derive(Clone, Copy, Debug, MessageResponse)]
+#[derive(Clone, Debug)]
 pub struct Player {
-    pub size: u32,
+    pub cells: Vec<Cell>,
+    // Center of the cells. Kept as the last position of the player once it died
     pub coordinates: Coordinates,
     pub viewport_size: Coordinates,
+    pub last_move: Instant,
+    pub state: PlayerState,
+    pub stats: PlayerStats,
+    // Secret that lets a client take the player back after its connection dropped
+    pub token: Uuid,
+    pub profile: Profile,
+    // When a client created the player or last took it back with its token
+    connected_at: Instant,
+    next_cell_id: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Cell {
+    pub id: u32,
+    pub size: u32,
+    pub coordinates: Coordinates,
+    // In world units per second. Given by a split and slowed down by SPLIT_DECELERATION
+    pub velocity: Velocity,
+    // The cell can merge back with the other cells of its player from this moment
+    pub merge_at: Instant,
+}
+
+/// Alive -> Dead when consumed, Dead -> Spectating once told about the death, and back to Alive on respawn
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PlayerState {
+    Alive,
+    Dead,
+    // Follows the player with the given id
+    Spectating(Uuid),
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct PlayerStats {
+    pub max_size: u32,
+    pub dots_eaten: u32,
+    pub players_eaten: u32,
+    pub spawned_at: Instant,
+}
+
+impl PlayerStats {
+    fn new() -> Self {
+        PlayerStats {
+            max_size: DEFAULT_PLAYER_SIZE,
+            dots_eaten: 0,
+            players_eaten: 0,
+            spawned_at: Instant::now(),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub struct CollisionData {
     pub win_id: Uuid,
-    pub win_size: u32,
+    pub win_cell: u32,
     pub lose_id: Uuid,
+    pub lose_cell: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct CellData {
+    pub id: u32,
+    pub coordinates: Coordinates,
+    pub size: u32,
+}
+
+/// Where a player looks at. Spectators look at the player they follow
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Viewport {
+    pub coordinates: Coordinates,
+    pub viewport_size: Coordinates,
+}
+
+/// How a player shows up to the others
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Profile {
+    pub nickname: String,
+    pub skin: u32,
+}
+
+/// Another player as seen from the viewport of a player. Only its visible cells are included
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlayerInViewport {
+    pub id: Uuid,
+    pub profile: Profile,
+    pub cells: Vec<CellData>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PlayerSummary {
+    pub id: Uuid,
+    pub coordinates: Coordinates,
+    pub size: u32,
+    pub token: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LeaderboardEntry {
+    pub id: Uuid,
+    pub profile: Profile,
+    pub size: u32,
+}
+
+/// The authoritative cells of a player whose state didn't match what the client expected.
+#[derive(Debug, Clone)]
+pub struct Correction {
+    pub cells: Vec<CellData>,
+}
+
+impl Cell {
+    fn new(id: u32, size: u32, coordinates: Coordinates) -> Self {
+        Cell {
+            id,
+            size,
+            coordinates,
+            velocity: Velocity::default(),
+            merge_at: Instant::now(),
+        }
+    }
+
+    // Bigger cells move slower, but never slower than PLAYER_MIN_SPEED
+    fn max_speed(&self) -> u32 {
+        cmp::max(
+            PLAYER_BASE_SPEED * DEFAULT_PLAYER_SIZE / cmp::max(self.size, 1),
+            PLAYER_MIN_SPEED,
+        )
+    }
+
+    fn max_distance(&self, elapsed: Duration) -> f64 {
+        f64::from(self.max_speed()) * elapsed.as_secs_f64() + f64::from(MOVE_TOLERANCE)
+    }
+
+    // The cell grown by the distance it can travel during elapsed, moving and gliding
+    fn reach(&self, elapsed: Duration) -> (Coordinates, u32) {
+        let glide = self.velocity.x.hypot(self.velocity.y) * elapsed.as_secs_f64();
+        let distance = (self.max_distance(elapsed) + glide).ceil() as u32;
+
+        (
+            Coordinates {
+                x: self.coordinates.x.saturating_sub(distance),
+                y: self.coordinates.y.saturating_sub(distance),
+            },
+            self.size.saturating_add(distance.saturating_mul(2)),
+        )
+    }
+
+    // Distance travelled thanks to the velocity during elapsed, which slows down the cell
+    fn glide(&mut self, elapsed: Duration) -> (i32, i32) {
+        self.velocity.glide(elapsed, SPLIT_DECELERATION)
+    }
+
+    // Where a dot shot with the given velocity starts, right outside of the cell
+    fn eject_position(&self, velocity: Velocity, world_size: Coordinates) -> Coordinates {
+        let distance = f64::from(self.size / 2 + DOT_SIZE) / EJECT_SPEED;
+        let center_x = self.coordinates.x + self.size / 2 - DOT_SIZE / 2;
+        let center_y = self.coordinates.y + self.size / 2 - DOT_SIZE / 2;
+
+        let (x, _clamped) = apply_delta(center_x, (velocity.x * distance) as i32, world_size.x - DOT_SIZE);
+        let (y, _clamped) = apply_delta(center_y, (velocity.y * distance) as i32, world_size.y - DOT_SIZE);
+
+        Coordinates { x, y }
+    }
+
+    fn overlaps(&self, other: &Cell) -> bool {
+        self.coordinates.x < other.coordinates.x + other.size
+            && self.coordinates.x + self.size > other.coordinates.x
+            && self.coordinates.y < other.coordinates.y + other.size
+            && self.coordinates.y + self.size > other.coordinates.y
+    }
+
+    fn data(&self) -> CellData {
+        CellData {
+            id: self.id,
+            coordinates: self.coordinates,
+            size: self.size,
+        }
+    }
 }
 
 impl Player {
-    fn new(viewport_size: Coordinates) -> Self {
+    fn new(viewport_size: Coordinates, profile: Profile, world_size: Coordinates) -> Self {
+        let coordinates = generate_coordinates(world_size);
+
         Player {
-            size: DEFAULT_PLAYER_SIZE,
-            coordinates: generate_coordinates(),
+            cells: vec![Cell::new(0, DEFAULT_PLAYER_SIZE, coordinates)],
+            coordinates,
             viewport_size,
+            last_move: Instant::now(),
+            state: PlayerState::Alive,
+            stats: PlayerStats::new(),
+            token: Uuid::new_v4(),
+            profile,
+            connected_at: Instant::now(),
+            next_cell_id: 1,
+        }
+    }
+
+    fn is_alive(&self) -> bool {
+        self.state == PlayerState::Alive
+    }
+
+    pub fn size(&self) -> u32 {
+        self.cells.iter().map(|cell| cell.size).sum()
+    }
+
+    fn cells_data(&self) -> Vec<CellData> {
+        self.cells.iter().map(Cell::data).collect()
+    }
+
+    // Has to be called whenever the cells change
+    fn update(&mut self) {
+        let size = self.size();
+        if size == 0 {
+            return;
+        }
+
+        let (x, y) = self.cells.iter().fold((0u64, 0u64), |(x, y), cell| {
+            (
+                x + u64::from(cell.coordinates.x) * u64::from(cell.size),
+                y + u64::from(cell.coordinates.y) * u64::from(cell.size),
+            )
+        });
+
+        self.coordinates = Coordinates {
+            x: (x / u64::from(size)) as u32,
+            y: (y / u64::from(size)) as u32,
+        };
+        self.stats.max_size = cmp::max(self.stats.max_size, size);
+    }
+
+    // Smallest square holding every cell of the player
+    fn bounds(&self) -> (Coordinates, u32) {
+        let min_x = self.cells.iter().map(|cell| cell.coordinates.x).min().unwrap_or(0);
+        let min_y = self.cells.iter().map(|cell| cell.coordinates.y).min().unwrap_or(0);
+        let max_x = self
+            .cells
+            .iter()
+            .map(|cell| cell.coordinates.x + cell.size)
+            .max()
+            .unwrap_or(0);
+        let max_y = self
+            .cells
+            .iter()
+            .map(|cell| cell.coordinates.y + cell.size)
+            .max()
+            .unwrap_or(0);
+
+        (
+            Coordinates { x: min_x, y: min_y },
+            cmp::max(max_x - min_x, max_y - min_y),
+        )
+    }
+
+    fn add_cell(&mut self, mut cell: Cell) {
+        cell.id = self.next_cell_id;
+        self.next_cell_id += 1;
+        self.cells.push(cell);
+    }
+
+    // Cells whose merge cooldown is over merge with the cells of the same player they touch
+    fn merge_cells(&mut self, now: Instant) -> bool {
+        let mut merged = false;
+        let mut index = 0;
+
+        while index < self.cells.len() {
+            let mut other_index = index + 1;
+
+            while other_index < self.cells.len() {
+                let (cell, other) = (&self.cells[index], &self.cells[other_index]);
+
+                if cell.merge_at <= now && other.merge_at <= now && cell.overlaps(other) {
+                    let other = self.cells.remove(other_index);
+                    self.cells[index].size += other.size;
+                    merged = true;
+                } else {
+                    other_index += 1;
+                }
+            }
+
+            index += 1;
         }
+
+        merged
+    }
+}
+
+// Scales a delta down so that its length doesn't exceed max_distance. Also tells whether it was scaled
+fn limit_delta(delta: Delta, max_distance: f64) -> (i32, i32, bool) {
+    let distance = f64::from(delta.x).hypot(f64::from(delta.y));
+
+    if distance > max_distance {
+        let ratio = max_distance / distance;
+        (
+            (f64::from(delta.x) * ratio) as i32,
+            (f64::from(delta.y) * ratio) as i32,
+            true,
+        )
+    } else {
+        (delta.x, delta.y, false)
     }
 }
 
@@ -77,42 +532,184 @@ impl Player {
 pub struct Players {
     pub players: HashMap<Uuid, Player>,
     pub players_count: u32,
+    // Index of the alive players by the region their cells cover. Has to be updated whenever cells change
+    pub grid: Grid<Uuid>,
+    world_size: Coordinates,
 }
 
-#[cfg(test)]
 impl Players {
-    fn new(players: HashMap<Uuid, Player>, players_count: u32) -> Self {
-        Players { players, players_count }
+    pub fn from_config(config: &Config) -> Self {
+        Players {
+            players: HashMap::new(),
+            players_count: 0,
+            grid: Grid::new(PLAYERS_GRID_BUCKET_SIZE, config.world_size),
+            world_size: config.world_size,
+        }
     }
 }
 
 impl Default for Players {
     fn default() -> Self {
-        Players {
-            players: HashMap::new(),
-            players_count: 0,
+        Players::from_config(&Config::default())
+    }
+}
+
+#[cfg(test)]
+impl Players {
+    pub fn new(players: HashMap<Uuid, Player>, players_count: u32) -> Self {
+        let ids: Vec<Uuid> = players.keys().copied().collect();
+        let mut result = Players {
+            players,
+            players_count,
+            ..Players::default()
+        };
+
+        for id in ids {
+            result.index_player(id);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+impl Player {
+    pub fn at(size: u32, coordinates: Coordinates, viewport_size: Coordinates) -> Self {
+        Player {
+            cells: vec![Cell::new(0, size, coordinates)],
+            coordinates,
+            viewport_size,
+            last_move: Instant::now() - Duration::from_secs(1),
+            state: PlayerState::Alive,
+            stats: PlayerStats::new(),
+            token: Uuid::new_v4(),
+            profile: Profile::default(),
+            connected_at: Instant::now(),
+            next_cell_id: 1,
+        }
+    }
+}
+
+impl Players {
+    fn remove_player(&mut self, id: Uuid) -> RemovePlayerResult {
+        let removed = match self.players.remove(&id) {
+            Some(player) => player,
+            None => return RemovePlayerResult { nearby: Vec::new() },
+        };
+        self.players_count -= 1;
+        metrics::PLAYERS.dec();
+        self.grid.remove(id);
+
+        if !removed.is_alive() {
+            return RemovePlayerResult { nearby: Vec::new() };
+        }
+
+        let nearby = self
+            .players
+            .iter()
+            .filter(|(_id, viewer)| self.is_in_viewport(viewer, &removed))
+            .map(|(id, _viewer)| *id)
+            .collect();
+
+        RemovePlayerResult { nearby }
+    }
+
+    fn index_player(&mut self, id: Uuid) {
+        match self.players.get(&id) {
+            Some(player) if player.is_alive() => {
+                let (coordinates, size) = player.bounds();
+                self.grid.insert(id, coordinates, size);
+            }
+            _ => self.grid.remove(id),
+        }
+    }
+
+    // Spectators see the world from the position of the player they follow
+    fn viewport_center(&self, player: &Player) -> Coordinates {
+        match player.state {
+            PlayerState::Spectating(target) => self
+                .players
+                .get(&target)
+                .map(|target| target.coordinates)
+                .unwrap_or(player.coordinates),
+            _ => player.coordinates,
         }
     }
+
+    // Top left and bottom right corners of the viewport
+    fn viewport_area(&self, viewer: &Player) -> (Coordinates, Coordinates) {
+        let center = self.viewport_center(viewer);
+
+        (
+            Coordinates {
+                x: (center.x).saturating_sub(viewer.viewport_size.x / 2),
+                y: (center.y).saturating_sub(viewer.viewport_size.y / 2),
+            },
+            Coordinates {
+                x: center.x.saturating_add(viewer.viewport_size.x / 2),
+                y: center.y.saturating_add(viewer.viewport_size.y / 2),
+            },
+        )
+    }
+
+    fn is_cell_in_viewport(&self, viewer: &Player, cell: &Cell) -> bool {
+        let (min, max) = self.viewport_area(viewer);
+
+        cell.coordinates.x + cell.size >= min.x
+            && (cell.coordinates.x < max.x)
+            && cell.coordinates.y + cell.size >= min.y
+            && cell.coordinates.y < max.y
+    }
+
+    fn is_in_viewport(&self, viewer: &Player, player: &Player) -> bool {
+        player.cells.iter().any(|cell| self.is_cell_in_viewport(viewer, cell))
+    }
 }
 
 impl Actor for Players {
     type Context = Context<Self>;
+
+    fn stopped(&mut self, _context: &mut Context<Self>) {
+        metrics::PLAYERS.sub(self.players.len() as i64);
+    }
 }
 
 impl Handler<CreatePlayer> for Players {
     type Result = CreatePlayerResult;
 
     fn handle(&mut self, message: CreatePlayer, _context: &mut Context<Self>) -> Self::Result {
-        let new_player = Player::new(message.0);
+        if let Some(token) = message.token {
+            let reconnected = self
+                .players
+                .iter_mut()
+                .find(|(_id, player)| player.token == token && player.is_alive());
+
+            if let Some((id, player)) = reconnected {
+                player.viewport_size = message.viewport_size;
+                player.connected_at = Instant::now();
+
+                return CreatePlayerResult {
+                    id: *id,
+                    coordinates: player.coordinates,
+                    token,
+                };
+            }
+        }
+
+        let new_player = Player::new(message.viewport_size, message.profile, self.world_size);
         let player_id = Uuid::new_v4();
+        let result = CreatePlayerResult {
+            id: player_id,
+            coordinates: new_player.coordinates,
+            token: new_player.token,
+        };
 
         self.players.insert(player_id, new_player);
         self.players_count += 1;
+        metrics::PLAYERS.inc();
+        self.index_player(player_id);
 
-        CreatePlayerResult {
-            id: player_id,
-            coordinates: new_player.coordinates,
-        }
+        result
     }
 }
 
@@ -120,87 +717,589 @@ impl Handler<MovePlayer> for Players {
     type Result = MovePlayerResult;
 
     fn handle(&mut self, message: MovePlayer, _context: &mut Context<Self>) -> Self::Result {
-        if let Some(player) = self.players.get_mut(&message.id) {
-            player.size = message.size;
-            player.coordinates.x += message.moved.x;
-            player.coordinates.y += message.moved.y;
+        let mut correction = None;
+        let world_size = self.world_size;
+
+        let player = match self.players.get_mut(&message.id) {
+            Some(player) if player.is_alive() => player,
+            _ => {
+                return MovePlayerResult {
+                    collisions: Vec::new(),
+                    correction,
+                    cells: Vec::new(),
+                }
+            }
+        };
+
+        let now = Instant::now();
+        let elapsed = cmp::min(now.duration_since(player.last_move), MAX_MOVE_INTERVAL);
+        let mut corrected = false;
+
+        // The size can only grow by the dots consumed since the last move. They are credited to the biggest cell
+        let allowed_size = player.size() + message.dots_mass;
+        if message.size != allowed_size {
+            corrected = true;
+        }
+        if let Some(biggest_cell) = player.cells.iter_mut().max_by_key(|cell| cell.size) {
+            biggest_cell.size += message.dots_mass;
+        }
+
+        // Every cell follows the requested move as fast as its size allows, plus what is left of its split velocity
+        for cell in player.cells.iter_mut() {
+            let (moved_x, moved_y, limited) = limit_delta(message.moved, cell.max_distance(elapsed));
+            let (glide_x, glide_y) = cell.glide(elapsed);
+
+            let (new_x, clamped_x) = apply_delta(
+                cell.coordinates.x,
+                moved_x.saturating_add(glide_x),
+                world_size.x.saturating_sub(cell.size),
+            );
+            let (new_y, clamped_y) = apply_delta(
+                cell.coordinates.y,
+                moved_y.saturating_add(glide_y),
+                world_size.y.saturating_sub(cell.size),
+            );
+            if limited || clamped_x || clamped_y {
+                corrected = true;
+            }
+
+            cell.coordinates = Coordinates { x: new_x, y: new_y };
+        }
+
+        if player.merge_cells(now) {
+            corrected = true;
+        }
+
+        player.stats.dots_eaten += message.dots_consumed;
+        player.last_move = now;
+        player.update();
+
+        if corrected {
+            correction = Some(Correction {
+                cells: player.cells_data(),
+            });
         }
 
+        let cells = player.cells_data();
+        let (coordinates, size) = player.bounds();
+        let mut collisions = Vec::new();
+
+        self.index_player(message.id);
+
+        // Only the players in the regions covered by the moved player can collide with it
+        let nearby = self.grid.query(
+            coordinates,
+            Coordinates {
+                x: coordinates.x + size,
+                y: coordinates.y + size,
+            },
+        );
+
         if let Some(player) = self.players.get(&message.id) {
-            for (player_id, player_data) in self.players.iter() {
-                if *player_id == message.id {
-                    continue;
-                }
+            for player_id in nearby.iter() {
+                let player_data = match self.players.get(player_id) {
+                    Some(player_data) if *player_id != message.id && player_data.is_alive() => player_data,
+                    _ => continue,
+                };
 
-                if player.coordinates.x < player_data.coordinates.x + player_data.size
-                    && player.coordinates.x + player.size > player_data.coordinates.x
-                    && player.coordinates.y < player_data.coordinates.y + player_data.size
-                    && player.coordinates.y + player.size > player_data.coordinates.y
-                {
-                    if player.size > player_data.size {
-                        return MovePlayerResult(Some(CollisionData {
-                            win_id: message.id,
-                            win_size: player.size + player_data.size,
-                            lose_id: *player_id,
-                        }));
-                    } else {
-                        return MovePlayerResult(None);
+                for cell in player.cells.iter() {
+                    for other_cell in player_data.cells.iter().filter(|other_cell| cell.overlaps(other_cell)) {
+                        if cell.size > other_cell.size {
+                            collisions.push(CollisionData {
+                                win_id: message.id,
+                                win_cell: cell.id,
+                                lose_id: *player_id,
+                                lose_cell: other_cell.id,
+                            });
+                        } else if cell.size < other_cell.size {
+                            collisions.push(CollisionData {
+                                win_id: *player_id,
+                                win_cell: other_cell.id,
+                                lose_id: message.id,
+                                lose_cell: cell.id,
+                            });
+                        }
                     }
                 }
             }
         }
 
-        MovePlayerResult(None)
+        MovePlayerResult {
+            collisions,
+            correction,
+            cells,
+        }
     }
 }
 
-impl Handler<GetPlayer> for Players {
-    type Result = Player;
-
-    fn handle(&mut self, message: GetPlayer, _context: &mut Context<Self>) -> Self::Result {
-        let matching_player = self.players.get(&message.0).unwrap();
+impl Handler<SplitPlayer> for Players {
+    type Result = SplitPlayerResult;
 
-        matching_player.clone()
-    }
-}
+    fn handle(&mut self, message: SplitPlayer, _context: &mut Context<Self>) -> Self::Result {
+        let player = match self.players.get_mut(&message.id) {
+            Some(player) if player.is_alive() => player,
+            _ => return SplitPlayerResult(None),
+        };
 
-impl Handler<GetPlayersInViewport> for Players {
-    type Result = GetPlayersInViewportResult;
+        let velocity = Velocity::towards(message.direction, SPLIT_SPEED);
+        let merge_at = Instant::now() + MERGE_COOLDOWN;
+        let cells_amount = player.cells.len();
+        let mut new_cells = Vec::new();
 
-    fn handle(&mut self, message: GetPlayersInViewport, _context: &mut Context<Self>) -> Self::Result {
-        if let Some(player) = self.players.get(&message.0) {
-            let min_x = (player.coordinates.x)
-                .checked_sub(player.viewport_size.x / 2)
-                .unwrap_or(0);
-            let max_x = player.coordinates.x + (player.viewport_size.x / 2);
-            let min_y = (player.coordinates.y)
-                .checked_sub(player.viewport_size.y / 2)
-                .unwrap_or(0);
-            let max_y = player.coordinates.y + (player.viewport_size.y / 2);
-
-            let players_in_viewport: Vec<(Coordinates, u32)> = self
-                .players
+        for cell in player.cells.iter_mut() {
+            if cells_amount + new_cells.len() >= MAX_CELLS_AMOUNT {
+                break;
+            }
+            if cell.size < MIN_SPLIT_SIZE {
+                continue;
+            }
+
+            let half = cell.size / 2;
+            cell.size -= half;
+            cell.merge_at = merge_at;
+
+            new_cells.push(Cell {
+                id: 0,
+                size: half,
+                coordinates: cell.coordinates,
+                velocity,
+                merge_at,
+            });
+        }
+
+        if new_cells.is_empty() {
+            return SplitPlayerResult(None);
+        }
+
+        for cell in new_cells {
+            player.add_cell(cell);
+        }
+        player.update();
+
+        let correction = Correction {
+            cells: player.cells_data(),
+        };
+        self.index_player(message.id);
+
+        SplitPlayerResult(Some(correction))
+    }
+}
+
+impl Handler<PopCell> for Players {
+    type Result = SplitPlayerResult;
+
+    fn handle(&mut self, message: PopCell, _context: &mut Context<Self>) -> Self::Result {
+        let player = match self.players.get_mut(&message.id) {
+            Some(player) if player.is_alive() => player,
+            _ => return SplitPlayerResult(None),
+        };
+        let cells_amount = player.cells.len();
+        let cell = match player.cells.iter_mut().find(|cell| cell.id == message.cell) {
+            Some(cell) => cell,
+            None => return SplitPlayerResult(None),
+        };
+
+        cell.size += message.mass;
+
+        // Every piece is at least DEFAULT_PLAYER_SIZE, and they are shot all around the popped cell
+        let pieces = cmp::min(
+            MAX_CELLS_AMOUNT - cells_amount + 1,
+            (cell.size / DEFAULT_PLAYER_SIZE) as usize,
+        ) as u32;
+        let piece_size = cell.size / cmp::max(pieces, 1);
+        let merge_at = Instant::now() + MERGE_COOLDOWN;
+        let mut new_cells = Vec::new();
+
+        for piece in 1..pieces {
+            let angle = 2.0 * std::f64::consts::PI * f64::from(piece) / f64::from(pieces);
+
+            cell.size -= piece_size;
+            cell.merge_at = merge_at;
+            new_cells.push(Cell {
+                id: 0,
+                size: piece_size,
+                coordinates: cell.coordinates,
+                velocity: Velocity {
+                    x: angle.cos() * SPLIT_SPEED,
+                    y: angle.sin() * SPLIT_SPEED,
+                },
+                merge_at,
+            });
+        }
+
+        for cell in new_cells {
+            player.add_cell(cell);
+        }
+        player.update();
+
+        let correction = Correction {
+            cells: player.cells_data(),
+        };
+        self.index_player(message.id);
+
+        SplitPlayerResult(Some(correction))
+    }
+}
+
+impl Handler<EjectMass> for Players {
+    type Result = EjectMassResult;
+
+    fn handle(&mut self, message: EjectMass, _context: &mut Context<Self>) -> Self::Result {
+        let world_size = self.world_size;
+        let player = match self.players.get_mut(&message.id) {
+            Some(player) if player.is_alive() => player,
+            _ => {
+                return EjectMassResult {
+                    correction: None,
+                    ejected: Vec::new(),
+                }
+            }
+        };
+
+        let velocity = Velocity::towards(message.direction, EJECT_SPEED);
+        let ejected: Vec<Dot> = player
+            .cells
+            .iter_mut()
+            .filter(|cell| cell.size >= MIN_EJECT_SIZE)
+            .map(|cell| {
+                cell.size -= EJECT_MASS;
+
+                Dot {
+                    coordinates: cell.eject_position(velocity, world_size),
+                    mass: EJECT_MASS,
+                    velocity,
+                }
+            })
+            .collect();
+
+        if ejected.is_empty() {
+            return EjectMassResult {
+                correction: None,
+                ejected,
+            };
+        }
+        player.update();
+
+        let correction = Correction {
+            cells: player.cells_data(),
+        };
+        self.index_player(message.id);
+
+        EjectMassResult {
+            correction: Some(correction),
+            ejected,
+        }
+    }
+}
+
+impl Handler<GetPlayer> for Players {
+    type Result = GetPlayerResult;
+
+    fn handle(&mut self, message: GetPlayer, _context: &mut Context<Self>) -> Self::Result {
+        GetPlayerResult(self.players.get(&message.0).map(|player| Viewport {
+            coordinates: self.viewport_center(player),
+            viewport_size: player.viewport_size,
+        }))
+    }
+}
+
+impl Handler<ConsumeCell> for Players {
+    type Result = ConsumeCellResult;
+
+    fn handle(&mut self, message: ConsumeCell, _context: &mut Context<Self>) -> Self::Result {
+        let collision = message.0;
+
+        let has_cell = |player: Option<&Player>, cell_id: u32| {
+            player.is_some_and(|player| player.is_alive() && player.cells.iter().any(|cell| cell.id == cell_id))
+        };
+        if !has_cell(self.players.get(&collision.win_id), collision.win_cell)
+            || !has_cell(self.players.get(&collision.lose_id), collision.lose_cell)
+        {
+            return ConsumeCellResult::NotApplied;
+        }
+
+        let loser = self.players.get_mut(&collision.lose_id).unwrap();
+        let lost_cell_index = loser
+            .cells
+            .iter()
+            .position(|cell| cell.id == collision.lose_cell)
+            .unwrap();
+        let lost_cell = loser.cells.remove(lost_cell_index);
+        let loser_died = loser.cells.is_empty();
+
+        if loser_died {
+            loser.state = PlayerState::Dead;
+        } else {
+            loser.update();
+        }
+        let loser_cells = loser.cells_data();
+        let loser_stats = loser.stats;
+
+        let winner = self.players.get_mut(&collision.win_id).unwrap();
+        if let Some(cell) = winner.cells.iter_mut().find(|cell| cell.id == collision.win_cell) {
+            cell.size += lost_cell.size;
+        }
+        if loser_died {
+            winner.stats.players_eaten += 1;
+        }
+        winner.update();
+        let winner_size = winner.size();
+
+        self.index_player(collision.win_id);
+        self.index_player(collision.lose_id);
+
+        if loser_died {
+            ConsumeCellResult::Died {
+                winner_size,
+                final_size: lost_cell.size,
+                stats: loser_stats,
+            }
+        } else {
+            ConsumeCellResult::Consumed {
+                winner_size,
+                loser_cells,
+            }
+        }
+    }
+}
+
+impl Handler<SpectatePlayer> for Players {
+    type Result = ();
+
+    fn handle(&mut self, message: SpectatePlayer, _context: &mut Context<Self>) {
+        if let Some(player) = self.players.get_mut(&message.id) {
+            if !player.is_alive() {
+                player.state = PlayerState::Spectating(message.target);
+            }
+        }
+    }
+}
+
+impl Handler<RespawnPlayer> for Players {
+    type Result = RespawnPlayerResult;
+
+    fn handle(&mut self, message: RespawnPlayer, _context: &mut Context<Self>) -> Self::Result {
+        match self.players.get_mut(&message.id) {
+            Some(player) if !player.is_alive() => {
+                *player = Player {
+                    token: player.token,
+                    ..Player::new(message.viewport_size, player.profile.clone(), self.world_size)
+                };
+
+                let result = CreatePlayerResult {
+                    id: message.id,
+                    coordinates: player.coordinates,
+                    token: player.token,
+                };
+                self.index_player(message.id);
+
+                RespawnPlayerResult(Some(result))
+            }
+            _ => RespawnPlayerResult(None),
+        }
+    }
+}
+
+impl Handler<RemovePlayer> for Players {
+    type Result = RemovePlayerResult;
+
+    fn handle(&mut self, message: RemovePlayer, _context: &mut Context<Self>) -> Self::Result {
+        self.remove_player(message.0)
+    }
+}
+
+impl Handler<RemoveDisconnectedPlayer> for Players {
+    type Result = Option<RemovePlayerResult>;
+
+    fn handle(&mut self, message: RemoveDisconnectedPlayer, _context: &mut Context<Self>) -> Self::Result {
+        let reconnected = self
+            .players
+            .get(&message.id)
+            .is_some_and(|player| player.connected_at > message.disconnected_at);
+        if reconnected {
+            return None;
+        }
+
+        Some(self.remove_player(message.id))
+    }
+}
+
+impl Handler<GetProfile> for Players {
+    type Result = GetProfileResult;
+
+    fn handle(&mut self, message: GetProfile, _context: &mut Context<Self>) -> Self::Result {
+        GetProfileResult(self.players.get(&message.0).map(|player| player.profile.clone()))
+    }
+}
+
+impl Handler<GetNearbyPlayers> for Players {
+    type Result = GetNearbyPlayersResult;
+
+    fn handle(&mut self, message: GetNearbyPlayers, _context: &mut Context<Self>) -> Self::Result {
+        let center = match self.players.get(&message.id) {
+            Some(player) => self.viewport_center(player),
+            None => return GetNearbyPlayersResult(Vec::new()),
+        };
+        let min = Coordinates {
+            x: center.x.saturating_sub(message.radius),
+            y: center.y.saturating_sub(message.radius),
+        };
+        let max = Coordinates {
+            x: center.x.saturating_add(message.radius),
+            y: center.y.saturating_add(message.radius),
+        };
+
+        let within_radius = |coordinates: Coordinates| {
+            let x = f64::from(coordinates.x) - f64::from(center.x);
+            let y = f64::from(coordinates.y) - f64::from(center.y);
+            x.hypot(y) <= f64::from(message.radius)
+        };
+
+        let mut nearby: Vec<Uuid> = self
+            .grid
+            .query(min, max)
+            .into_iter()
+            .filter(|id| *id != message.id)
+            .filter(|id| {
+                self.players
+                    .get(id)
+                    .is_some_and(|player| within_radius(player.coordinates))
+            })
+            .collect();
+        // Only the alive players are in the grid
+        nearby.extend(
+            self.players
                 .iter()
                 .filter(|(id, player)| {
-                    **id != message.0
-                        && player.coordinates.x + player.size >= min_x
-                        && (player.coordinates.x < max_x)
-                        && player.coordinates.y + player.size >= min_y
-                        && player.coordinates.y < max_y
+                    **id != message.id && !player.is_alive() && within_radius(self.viewport_center(player))
                 })
-                .map(|(_id, player)| (player.coordinates, player.size))
+                .map(|(id, _player)| *id),
+        );
+
+        GetNearbyPlayersResult(nearby)
+    }
+}
+
+impl Handler<GetLeaderboard> for Players {
+    type Result = GetLeaderboardResult;
+
+    fn handle(&mut self, _message: GetLeaderboard, _context: &mut Context<Self>) -> Self::Result {
+        let mut ranking: Vec<LeaderboardEntry> = self
+            .players
+            .iter()
+            .filter(|(_id, player)| player.is_alive())
+            .map(|(id, player)| LeaderboardEntry {
+                id: *id,
+                profile: player.profile.clone(),
+                size: player.size(),
+            })
+            .collect();
+        // Ties are broken by id so that the order doesn't change between two broadcasts
+        ranking.sort_by(|first, second| second.size.cmp(&first.size).then(first.id.cmp(&second.id)));
+
+        let ranks = ranking
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| (entry.id, index as u32 + 1))
+            .collect();
+        ranking.truncate(LEADERBOARD_SIZE);
+
+        GetLeaderboardResult {
+            leaders: ranking,
+            ranks,
+        }
+    }
+}
+
+impl Handler<GetReach> for Players {
+    type Result = GetReachResult;
+
+    fn handle(&mut self, message: GetReach, _context: &mut Context<Self>) -> Self::Result {
+        let player = match self.players.get(&message.0) {
+            Some(player) if player.is_alive() => player,
+            _ => return GetReachResult(Vec::new()),
+        };
+        let elapsed = cmp::min(player.last_move.elapsed(), MAX_MOVE_INTERVAL);
+
+        GetReachResult(player.cells.iter().map(|cell| cell.reach(elapsed)).collect())
+    }
+}
+
+impl Handler<GetPlayersSummaries> for Players {
+    type Result = GetPlayersSummariesResult;
+
+    fn handle(&mut self, message: GetPlayersSummaries, _context: &mut Context<Self>) -> Self::Result {
+        let summaries = message
+            .0
+            .into_iter()
+            .filter_map(|id| {
+                self.players.get(&id).map(|player| PlayerSummary {
+                    id,
+                    coordinates: player.coordinates,
+                    size: player.size(),
+                    token: player.token,
+                })
+            })
+            .collect();
+
+        GetPlayersSummariesResult(summaries)
+    }
+}
+
+impl Handler<GetPlayersInViewport> for Players {
+    type Result = GetPlayersInViewportResult;
+
+    fn handle(&mut self, message: GetPlayersInViewport, _context: &mut Context<Self>) -> Self::Result {
+        if let Some(viewer) = self.players.get(&message.0) {
+            let (min, max) = self.viewport_area(viewer);
+            let players_in_viewport: Vec<PlayerInViewport> = self
+                .grid
+                .query(min, max)
+                .into_iter()
+                .filter_map(|id| self.players.get(&id).map(|player| (id, player)))
+                .filter(|(id, player)| *id != message.0 && player.is_alive())
+                .map(|(id, player)| PlayerInViewport {
+                    id,
+                    profile: player.profile.clone(),
+                    cells: player
+                        .cells
+                        .iter()
+                        .filter(|cell| self.is_cell_in_viewport(viewer, cell))
+                        .map(Cell::data)
+                        .collect(),
+                })
+                .filter(|player| !player.cells.is_empty())
                 .collect();
 
-            return GetPlayersInViewportResult(players_in_viewport);
+            return GetPlayersInViewportResult {
+                players: players_in_viewport,
+            };
         }
 
-        GetPlayersInViewportResult(Vec::new())
+        GetPlayersInViewportResult { players: Vec::new() }
+    }
+}
+
+impl Handler<Shutdown> for Players {
+    type Result = ();
+
+    fn handle(&mut self, _message: Shutdown, context: &mut Context<Self>) {
+        context.stop();
+    }
+}
+
+impl Handler<Ping> for Players {
+    type Result = Result<(), MailboxError>;
+
+    fn handle(&mut self, _message: Ping, _context: &mut Context<Self>) -> Self::Result {
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::consts::{DOT_MASS, WORLD_X_SIZE, WORLD_Y_SIZE};
     use futures::{future, Future};
     use std::sync::Arc;
 
@@ -212,12 +1311,7 @@ mod tests {
         type Result = Players;
 
         fn handle(&mut self, _message: GetState, _context: &mut Context<Self>) -> Players {
-            let players = self.players.clone();
-
-            Players {
-                players,
-                players_count: self.players_count,
-            }
+            self.clone()
         }
     }
 
@@ -236,14 +1330,24 @@ mod tests {
                 assert_eq!(result.players_count, 0);
                 future::ok(())
             })
-            .and_then(|_future| player_actor.send(CreatePlayer(viewport_size)))
+            .and_then(|_future| {
+                player_actor.send(CreatePlayer {
+                    viewport_size,
+                    token: None,
+                    profile: Profile::default(),
+                })
+            })
             .and_then(|result| {
                 assert_eq!(result.coordinates, Coordinates { x: 100, y: 100 });
                 player_actor.send(GetState)
             })
             .and_then(|result| {
                 assert_eq!(result.players_count, 1);
-                player_actor.send(CreatePlayer(viewport_size))
+                player_actor.send(CreatePlayer {
+                    viewport_size,
+                    token: None,
+                    profile: Profile::default(),
+                })
             })
             .and_then(|_result| player_actor.send(GetState))
             .map(|result| {
@@ -266,33 +1370,29 @@ mod tests {
 
         initial_players.insert(
             first_player_id,
-            Player {
-                size: 10,
-                coordinates: Coordinates { x: 200, y: 200 },
-                viewport_size: Coordinates { x: 1000, y: 1000 },
-            },
+            Player::at(10, Coordinates { x: 200, y: 200 }, Coordinates { x: 1000, y: 1000 }),
         );
         initial_players.insert(
             second_player_id,
-            Player {
-                size: 20,
-                coordinates: Coordinates { x: 250, y: 250 },
-                viewport_size: Coordinates { x: 1000, y: 1000 },
-            },
+            Player::at(20, Coordinates { x: 250, y: 250 }, Coordinates { x: 1000, y: 1000 }),
         );
 
         let player_actor = Arc::new(Players::new(initial_players, 2).start());
 
         player_actor.do_send(MovePlayer {
             id: first_player_id,
-            moved: Coordinates { x: 10, y: 10 },
+            moved: Delta { x: 10, y: 10 },
             size: 10,
+            dots_consumed: 0,
+            dots_mass: 0,
         });
 
         player_actor.do_send(MovePlayer {
             id: second_player_id,
-            moved: Coordinates { x: 50, y: 40 },
+            moved: Delta { x: 50, y: 40 },
             size: 15,
+            dots_consumed: 0,
+            dots_mass: 0,
         });
 
         let move_player_future = player_actor.send(GetState).map(|result| {
@@ -302,10 +1402,11 @@ mod tests {
             let first_player = result.players.get(&first_player_id).unwrap();
             let second_player = result.players.get(&second_player_id).unwrap();
 
-            assert_eq!(first_player.size, 10);
+            assert_eq!(first_player.size(), 10);
             assert_eq!(first_player.coordinates, Coordinates { x: 210, y: 210 });
 
-            assert_eq!(second_player.size, 15);
+            // The size sent by the client is ignored, only the dots consumed make a player grow
+            assert_eq!(second_player.size(), 20);
             assert_eq!(second_player.coordinates, Coordinates { x: 300, y: 290 });
         });
 
@@ -313,8 +1414,63 @@ mod tests {
     }
 
     #[test]
-    fn test_get_players_in_viewport() {
-        let mut system = System::new("players_in_viewport");
+    fn test_players_actor_move_backwards() {
+        let mut system = System::new("players_moving_backwards");
+
+        let mut initial_players = HashMap::new();
+        let first_player_id =
+            Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").expect("Couldn't parse first player id");
+        let second_player_id =
+            Uuid::parse_str("78a40100-4dc3-46e4-8a91-00e0316586e4").expect("Couldn't parse second player id");
+
+        initial_players.insert(
+            first_player_id,
+            Player::at(10, Coordinates { x: 200, y: 200 }, Coordinates { x: 1000, y: 1000 }),
+        );
+        initial_players.insert(
+            second_player_id,
+            Player::at(20, Coordinates { x: 250, y: 250 }, Coordinates { x: 1000, y: 1000 }),
+        );
+
+        let player_actor = Arc::new(Players::new(initial_players, 2).start());
+
+        // Deltas are signed, so cells can move towards the top left corner
+        player_actor.do_send(MovePlayer {
+            id: first_player_id,
+            moved: Delta { x: -10, y: -10 },
+            size: 10,
+            dots_consumed: 0,
+            dots_mass: 0,
+        });
+
+        player_actor.do_send(MovePlayer {
+            id: second_player_id,
+            moved: Delta { x: -50, y: 40 },
+            size: 15,
+            dots_consumed: 0,
+            dots_mass: 0,
+        });
+
+        let move_player_future = player_actor.send(GetState).map(|result| {
+            assert_eq!(result.players.len(), 2);
+            assert_eq!(result.players_count, 2);
+
+            let first_player = result.players.get(&first_player_id).unwrap();
+            let second_player = result.players.get(&second_player_id).unwrap();
+
+            assert_eq!(first_player.size(), 10);
+            assert_eq!(first_player.coordinates, Coordinates { x: 190, y: 190 });
+
+            assert_eq!(second_player.size(), 20);
+            assert_eq!(second_player.coordinates, Coordinates { x: 200, y: 290 });
+        });
+
+        system.block_on(move_player_future).expect("System error");
+    }
+
+    #[test]
+    fn test_players_actor_move_validation() {
+        let mut system = System::new("players_move_validation");
 
         let mut initial_players = HashMap::new();
         let first_player_id =
@@ -326,54 +1482,812 @@ mod tests {
 
         initial_players.insert(
             first_player_id,
-            Player {
-                size: 10,
-                coordinates: Coordinates { x: 200, y: 200 },
-                viewport_size: Coordinates { x: 500, y: 500 },
-            },
+            Player::at(20, Coordinates { x: 200, y: 200 }, Coordinates { x: 1000, y: 1000 }),
         );
         initial_players.insert(
             second_player_id,
-            Player {
-                size: 20,
-                coordinates: Coordinates { x: 200, y: 250 },
-                viewport_size: Coordinates { x: 500, y: 500 },
-            },
+            Player::at(
+                20,
+                Coordinates {
+                    x: WORLD_X_SIZE - 30,
+                    y: 5000,
+                },
+                Coordinates { x: 1000, y: 1000 },
+            ),
         );
+
         initial_players.insert(
             third_player_id,
-            Player {
-                size: 50,
-                coordinates: Coordinates { x: 200, y: 300 },
-                viewport_size: Coordinates { x: 500, y: 500 },
-            },
+            Player::at(20, Coordinates { x: 5, y: 30 }, Coordinates { x: 1000, y: 1000 }),
         );
 
         let player_actor = Arc::new(Players::new(initial_players, 3).start());
 
+        let move_player_future = player_actor
+            .send(MovePlayer {
+                id: first_player_id,
+                moved: Delta { x: 5000, y: 0 },
+                size: 25,
+                dots_consumed: 2,
+                dots_mass: 2 * DOT_MASS,
+            })
+            .and_then(|result: MovePlayerResult| {
+                let correction = result.correction.expect("Teleport wasn't corrected");
+                let max_distance =
+                    f64::from(PLAYER_BASE_SPEED) * MAX_MOVE_INTERVAL.as_secs_f64() + f64::from(MOVE_TOLERANCE);
+
+                assert_eq!(correction.cells[0].size, 20 + 2 * DOT_MASS);
+                assert!(f64::from(correction.cells[0].coordinates.x - 200) <= max_distance);
+                assert_eq!(correction.cells[0].coordinates.y, 200);
+
+                player_actor.send(MovePlayer {
+                    id: second_player_id,
+                    moved: Delta { x: 100, y: 0 },
+                    size: 20,
+                    dots_consumed: 0,
+                    dots_mass: 0,
+                })
+            })
+            .and_then(|result: MovePlayerResult| {
+                let correction = result.correction.expect("Move out of the world wasn't corrected");
+
+                assert_eq!(correction.cells[0].coordinates.x, WORLD_X_SIZE - 20);
+
+                player_actor.send(MovePlayer {
+                    id: second_player_id,
+                    moved: Delta { x: 0, y: -5 },
+                    size: 20,
+                    dots_consumed: 0,
+                    dots_mass: 0,
+                })
+            })
+            .and_then(|result: MovePlayerResult| {
+                assert!(result.correction.is_none());
+
+                player_actor.send(MovePlayer {
+                    id: first_player_id,
+                    moved: Delta { x: -5, y: -8 },
+                    size: 22,
+                    dots_consumed: 0,
+                    dots_mass: 0,
+                })
+            })
+            .and_then(|result: MovePlayerResult| {
+                assert!(result.correction.is_none());
+
+                player_actor.send(MovePlayer {
+                    id: third_player_id,
+                    moved: Delta { x: -50, y: -20 },
+                    size: 20,
+                    dots_consumed: 0,
+                    dots_mass: 0,
+                })
+            })
+            .and_then(|result: MovePlayerResult| {
+                let correction = result.correction.expect("Move out of the world wasn't corrected");
+
+                assert_eq!(correction.cells[0].coordinates, Coordinates { x: 0, y: 10 });
+                player_actor.send(GetState)
+            })
+            .map(move |result: Players| {
+                let second_player = result.players.get(&second_player_id).unwrap();
+
+                assert_eq!(second_player.coordinates.y, 4995);
+            });
+
+        system.block_on(move_player_future).expect("System error");
+    }
+
+    #[test]
+    fn test_get_players_in_huge_viewport() {
+        let mut system = System::new("players_in_huge_viewport");
+
+        let mut initial_players = HashMap::new();
+        let first_player_id =
+            Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").expect("Couldn't parse first player id");
+        let second_player_id =
+            Uuid::parse_str("78a40100-4dc3-46e4-8a91-00e0316586e4").expect("Couldn't parse second player id");
+
+        // Clients can't ask for such a viewport, but the players actor doesn't overflow nor hang on it
+        initial_players.insert(
+            first_player_id,
+            Player::at(
+                10,
+                Coordinates {
+                    x: WORLD_X_SIZE - 10,
+                    y: WORLD_Y_SIZE - 10,
+                },
+                Coordinates {
+                    x: u32::MAX,
+                    y: u32::MAX,
+                },
+            ),
+        );
+        initial_players.insert(
+            second_player_id,
+            Player::at(20, Coordinates { x: 0, y: 0 }, Coordinates { x: 500, y: 500 }),
+        );
+
+        let player_actor = Players::new(initial_players, 2).start();
+
         let get_players_in_viewport_future =
             player_actor
                 .send(GetPlayersInViewport(first_player_id))
-                .and_then(|result: GetPlayersInViewportResult| {
-                    let expected_vec = vec![
-                        (Coordinates { x: 200, y: 250 }, 20),
-                        (Coordinates { x: 200, y: 300 }, 50),
-                    ];
-
-                    assert_eq!(result.0.len(), expected_vec.len());
-                    for player_data in expected_vec.iter() {
-                        assert_eq!(result.0.contains(player_data), true);
+                .map(move |result: GetPlayersInViewportResult| {
+                    assert_eq!(result.players.len(), 1);
+                    assert_eq!(result.players[0].id, second_player_id);
+                });
+
+        system.block_on(get_players_in_viewport_future).expect("System error");
+    }
+
+    #[test]
+    fn test_get_players_in_viewport() {
+        let mut system = System::new("players_in_viewport");
+
+        let mut initial_players = HashMap::new();
+        let first_player_id =
+            Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").expect("Couldn't parse first player id");
+        let second_player_id =
+            Uuid::parse_str("78a40100-4dc3-46e4-8a91-00e0316586e4").expect("Couldn't parse second player id");
+        let third_player_id =
+            Uuid::parse_str("1f4c367c-f35f-4eda-8cb1-c4494fb542ab").expect("Couldn't parse the third player id");
+
+        initial_players.insert(
+            first_player_id,
+            Player::at(10, Coordinates { x: 200, y: 200 }, Coordinates { x: 500, y: 500 }),
+        );
+        initial_players.insert(
+            second_player_id,
+            Player::at(20, Coordinates { x: 200, y: 250 }, Coordinates { x: 500, y: 500 }),
+        );
+        initial_players.insert(
+            third_player_id,
+            Player::at(50, Coordinates { x: 200, y: 300 }, Coordinates { x: 500, y: 500 }),
+        );
+
+        let player_actor = Arc::new(Players::new(initial_players, 3).start());
+
+        let get_players_in_viewport_future = player_actor.send(GetPlayersInViewport(first_player_id)).and_then(
+            move |result: GetPlayersInViewportResult| {
+                let expected_vec = [
+                    PlayerInViewport {
+                        id: second_player_id,
+                        profile: Profile::default(),
+                        cells: vec![CellData {
+                            id: 0,
+                            coordinates: Coordinates { x: 200, y: 250 },
+                            size: 20,
+                        }],
+                    },
+                    PlayerInViewport {
+                        id: third_player_id,
+                        profile: Profile::default(),
+                        cells: vec![CellData {
+                            id: 0,
+                            coordinates: Coordinates { x: 200, y: 300 },
+                            size: 50,
+                        }],
+                    },
+                ];
+
+                assert_eq!(result.players.len(), expected_vec.len());
+                for player_data in expected_vec.iter() {
+                    assert!(result.players.contains(player_data));
+                }
+
+                future::ok(())
+            },
+        );
+
+        system.block_on(get_players_in_viewport_future).expect("System error");
+    }
+
+    #[test]
+    fn test_players_actor_nearby() {
+        let mut system = System::new("players_nearby");
+
+        let mut initial_players = HashMap::new();
+        let sender_id = Uuid::new_v4();
+        let near_id = Uuid::new_v4();
+        let far_id = Uuid::new_v4();
+        let dead_id = Uuid::new_v4();
+        let spectator_id = Uuid::new_v4();
+        let far_spectator_id = Uuid::new_v4();
+        let viewport_size = Coordinates { x: 1000, y: 1000 };
+
+        initial_players.insert(
+            sender_id,
+            Player::at(20, Coordinates { x: 5000, y: 5000 }, viewport_size),
+        );
+        initial_players.insert(near_id, Player::at(20, Coordinates { x: 5300, y: 5400 }, viewport_size));
+        // Inside the square around the sender, but not within the radius
+        initial_players.insert(far_id, Player::at(20, Coordinates { x: 5400, y: 5400 }, viewport_size));
+        // The players that aren't alive hear the chat around what they look at
+        let mut dead_player = Player::at(20, Coordinates { x: 5100, y: 5000 }, viewport_size);
+        dead_player.state = PlayerState::Dead;
+        initial_players.insert(dead_id, dead_player);
+        let mut spectator = Player::at(20, Coordinates { x: 15000, y: 15000 }, viewport_size);
+        spectator.state = PlayerState::Spectating(near_id);
+        initial_players.insert(spectator_id, spectator);
+        let mut far_spectator = Player::at(20, Coordinates { x: 5000, y: 5000 }, viewport_size);
+        far_spectator.state = PlayerState::Spectating(far_id);
+        initial_players.insert(far_spectator_id, far_spectator);
+
+        let player_actor = Players::new(initial_players, 6).start();
+
+        let nearby_future = player_actor
+            .send(GetNearbyPlayers {
+                id: sender_id,
+                radius: 500,
+            })
+            .map(move |result| {
+                let mut expected = vec![near_id, dead_id, spectator_id];
+                let mut nearby = result.0;
+                expected.sort();
+                nearby.sort();
+                assert_eq!(nearby, expected);
+            });
+
+        system.block_on(nearby_future).expect("System error");
+    }
+
+    #[test]
+    fn test_players_actor_leaderboard() {
+        let mut system = System::new("players_leaderboard");
+
+        let mut initial_players = HashMap::new();
+        let mut ids = Vec::new();
+        for size in 1..=12 {
+            let id = Uuid::new_v4();
+            ids.push(id);
+            initial_players.insert(
+                id,
+                Player::at(
+                    size * 10,
+                    Coordinates { x: 200, y: 200 },
+                    Coordinates { x: 1000, y: 1000 },
+                ),
+            );
+        }
+        // The biggest player is dead and isn't ranked
+        let dead_id = ids[11];
+        initial_players.get_mut(&dead_id).unwrap().state = PlayerState::Dead;
+
+        let player_actor = Players::new(initial_players, 12).start();
+
+        let leaderboard_future = player_actor.send(GetLeaderboard).map(move |result| {
+            let sizes: Vec<u32> = result.leaders.iter().map(|entry| entry.size).collect();
+
+            assert_eq!(sizes, vec![110, 100, 90, 80, 70, 60, 50, 40, 30, 20]);
+            assert_eq!(result.leaders[0].id, ids[10]);
+            assert_eq!(result.ranks.len(), 11);
+            assert_eq!(result.ranks[&ids[10]], 1);
+            assert_eq!(result.ranks[&ids[0]], 11);
+            assert_eq!(result.ranks.get(&dead_id), None);
+        });
+
+        system.block_on(leaderboard_future).expect("System error");
+    }
+
+    fn collision_players() -> (Uuid, Uuid, HashMap<Uuid, Player>) {
+        let mut initial_players = HashMap::new();
+        let small_player_id =
+            Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").expect("Couldn't parse small player id");
+        let big_player_id =
+            Uuid::parse_str("78a40100-4dc3-46e4-8a91-00e0316586e4").expect("Couldn't parse big player id");
+
+        initial_players.insert(
+            small_player_id,
+            Player::at(20, Coordinates { x: 200, y: 200 }, Coordinates { x: 1000, y: 1000 }),
+        );
+        initial_players.insert(
+            big_player_id,
+            Player::at(40, Coordinates { x: 250, y: 200 }, Coordinates { x: 1000, y: 1000 }),
+        );
+
+        (small_player_id, big_player_id, initial_players)
+    }
+
+    #[test]
+    fn test_players_actor_summaries() {
+        let mut system = System::new("players_summaries");
+        let (small_player_id, big_player_id, initial_players) = collision_players();
+        let player_actor = Players::new(initial_players, 2).start();
+
+        let summaries_future = player_actor
+            .send(GetPlayersSummaries(vec![big_player_id, Uuid::new_v4()]))
+            .map(move |result| {
+                assert_eq!(result.0.len(), 1);
+                assert_eq!(result.0[0].id, big_player_id);
+                assert_eq!(result.0[0].size, 40);
+                assert_ne!(result.0[0].id, small_player_id);
+            });
+
+        system.block_on(summaries_future).expect("System error");
+    }
+
+    #[test]
+    fn test_players_actor_remove_disconnected() {
+        let mut system = System::new("players_remove_disconnected");
+        let (small_player_id, big_player_id, initial_players) = collision_players();
+        let token = initial_players.get(&small_player_id).unwrap().token;
+        let player_actor = Arc::new(Players::new(initial_players, 2).start());
+        let disconnected_at = Instant::now();
+
+        let remove_future = player_actor
+            .send(CreatePlayer {
+                viewport_size: Coordinates { x: 1000, y: 1000 },
+                token: Some(token),
+                profile: Profile::default(),
+            })
+            .and_then(move |result: CreatePlayerResult| {
+                assert_eq!(result.id, small_player_id);
+
+                // The grace period ended while the client was taking the player back
+                player_actor
+                    .send(RemoveDisconnectedPlayer {
+                        id: small_player_id,
+                        disconnected_at,
+                    })
+                    .join(player_actor.send(RemoveDisconnectedPlayer {
+                        id: big_player_id,
+                        disconnected_at: Instant::now(),
+                    }))
+                    .and_then(move |results| player_actor.send(GetState).map(|state: Players| (results, state)))
+            })
+            .map(move |((small_result, big_result), result)| {
+                assert!(small_result.is_none());
+                assert!(big_result.is_some());
+                assert!(result.players.contains_key(&small_player_id));
+                assert!(!result.players.contains_key(&big_player_id));
+            });
+
+        system.block_on(remove_future).expect("System error");
+    }
+
+    #[test]
+    fn test_players_actor_win() {
+        let mut system = System::new("players_win");
+        let (small_player_id, big_player_id, initial_players) = collision_players();
+        let player_actor = Arc::new(Players::new(initial_players, 2).start());
+
+        let win_future = player_actor
+            .send(MovePlayer {
+                id: big_player_id,
+                moved: Delta { x: -40, y: 0 },
+                size: 40,
+                dots_consumed: 0,
+                dots_mass: 0,
+            })
+            .and_then(|result: MovePlayerResult| {
+                assert_eq!(result.collisions.len(), 1);
+                let collision = result.collisions[0];
+
+                assert_eq!(collision.win_id, big_player_id);
+                assert_eq!(collision.lose_id, small_player_id);
+
+                player_actor.send(ConsumeCell(collision))
+            })
+            .and_then(|result: ConsumeCellResult| {
+                match result {
+                    ConsumeCellResult::Died {
+                        winner_size,
+                        final_size,
+                        ..
+                    } => {
+                        assert_eq!(winner_size, 60);
+                        assert_eq!(final_size, 20);
                     }
+                    _ => panic!("Loser didn't die"),
+                }
+                player_actor.send(GetState)
+            })
+            .map(move |result: Players| {
+                let winner = result.players.get(&big_player_id).unwrap();
+
+                assert_eq!(result.players_count, 2);
+                assert_eq!(result.players.get(&small_player_id).unwrap().state, PlayerState::Dead);
+                assert_eq!(winner.size(), 60);
+                assert_eq!(winner.stats.max_size, 60);
+                assert_eq!(winner.stats.players_eaten, 1);
+
+                // Dead players can't be found in the grid anymore
+                let indexed = result
+                    .grid
+                    .query(Coordinates { x: 0, y: 0 }, Coordinates { x: 1000, y: 1000 });
+                assert!(indexed.contains(&big_player_id));
+                assert!(!indexed.contains(&small_player_id));
+            });
+
+        system.block_on(win_future).expect("System error");
+    }
+
+    #[test]
+    fn test_players_actor_lose() {
+        let mut system = System::new("players_lose");
+        let (small_player_id, big_player_id, initial_players) = collision_players();
+        let player_actor = Arc::new(Players::new(initial_players, 2).start());
+
+        let lose_future = player_actor
+            .send(MovePlayer {
+                id: small_player_id,
+                moved: Delta { x: 40, y: 0 },
+                size: 20,
+                dots_consumed: 0,
+                dots_mass: 0,
+            })
+            .and_then(|result: MovePlayerResult| {
+                assert_eq!(result.collisions.len(), 1);
+                let collision = result.collisions[0];
+
+                assert_eq!(collision.win_id, big_player_id);
+                assert_eq!(collision.lose_id, small_player_id);
+
+                player_actor
+                    .send(ConsumeCell(collision))
+                    .and_then(move |_result| player_actor.send(ConsumeCell(collision)))
+            })
+            .map(|result: ConsumeCellResult| match result {
+                ConsumeCellResult::NotApplied => {}
+                _ => panic!("Collision was applied twice"),
+            });
+
+        system.block_on(lose_future).expect("System error");
+    }
+
+    #[test]
+    fn test_players_actor_spectate_and_respawn() {
+        let mut system = System::new("players_respawn");
+        let (small_player_id, big_player_id, initial_players) = collision_players();
+        let player_actor = Arc::new(Players::new(initial_players, 2).start());
+        let viewport_size = Coordinates { x: 800, y: 600 };
 
-                    future::ok(())
+        let respawn_future = player_actor
+            .send(RespawnPlayer {
+                id: small_player_id,
+                viewport_size,
+            })
+            .and_then(|result: RespawnPlayerResult| {
+                assert!(result.0.is_none());
+
+                player_actor.send(ConsumeCell(CollisionData {
+                    win_id: big_player_id,
+                    win_cell: 0,
+                    lose_id: small_player_id,
+                    lose_cell: 0,
+                }))
+            })
+            .and_then(|_result| {
+                player_actor.do_send(SpectatePlayer {
+                    id: small_player_id,
+                    target: big_player_id,
                 });
+                player_actor.send(GetPlayer(small_player_id))
+            })
+            .and_then(|result: GetPlayerResult| {
+                assert_eq!(result.0.unwrap().coordinates, Coordinates { x: 250, y: 200 });
 
-        system.block_on(get_players_in_viewport_future).expect("System error");
+                player_actor.send(MovePlayer {
+                    id: small_player_id,
+                    moved: Delta { x: 5, y: 0 },
+                    size: 20,
+                    dots_consumed: 0,
+                    dots_mass: 0,
+                })
+            })
+            .and_then(|result: MovePlayerResult| {
+                assert!(result.collisions.is_empty());
+                assert!(result.correction.is_none());
+
+                player_actor.send(RespawnPlayer {
+                    id: small_player_id,
+                    viewport_size,
+                })
+            })
+            .and_then(|result: RespawnPlayerResult| {
+                let respawned = result.0.expect("Player wasn't respawned");
+
+                assert_eq!(respawned.id, small_player_id);
+                player_actor.send(GetState)
+            })
+            .map(move |result: Players| {
+                let player = result.players.get(&small_player_id).unwrap();
+
+                assert_eq!(result.players_count, 2);
+                assert_eq!(player.state, PlayerState::Alive);
+                assert_eq!(player.size(), DEFAULT_PLAYER_SIZE);
+                assert_eq!(player.viewport_size, viewport_size);
+                assert_eq!(player.stats.players_eaten, 0);
+            });
+
+        system.block_on(respawn_future).expect("System error");
     }
 
     #[test]
-    fn test_players_actor_win() {}
+    fn test_players_actor_remove() {
+        let mut system = System::new("players_removal");
+        let (small_player_id, big_player_id, mut initial_players) = collision_players();
+        let far_player_id =
+            Uuid::parse_str("1f4c367c-f35f-4eda-8cb1-c4494fb542ab").expect("Couldn't parse far player id");
+
+        initial_players.insert(
+            far_player_id,
+            Player::at(20, Coordinates { x: 9000, y: 9000 }, Coordinates { x: 1000, y: 1000 }),
+        );
+
+        let player_actor = Arc::new(Players::new(initial_players, 3).start());
+
+        let remove_future = player_actor
+            .send(RemovePlayer(small_player_id))
+            .and_then(|result: RemovePlayerResult| {
+                assert_eq!(result.nearby, vec![big_player_id]);
+                player_actor.send(RemovePlayer(small_player_id))
+            })
+            .and_then(|result: RemovePlayerResult| {
+                assert!(result.nearby.is_empty());
+                player_actor.send(GetState)
+            })
+            .map(move |result: Players| {
+                let indexed = result.grid.query(
+                    Coordinates { x: 0, y: 0 },
+                    Coordinates {
+                        x: WORLD_X_SIZE,
+                        y: WORLD_Y_SIZE,
+                    },
+                );
+
+                assert_eq!(result.players_count, 2);
+                assert!(!result.players.contains_key(&small_player_id));
+                assert_eq!(indexed.len(), 2);
+            });
+
+        system.block_on(remove_future).expect("System error");
+    }
 
     #[test]
-    fn test_players_actor_lose() {}
+    fn test_players_actor_reconnect() {
+        let mut system = System::new("players_reconnection");
+        let player_actor = Arc::new(Players::default().start());
+        let viewport_size = Coordinates { x: 1000, y: 1000 };
+
+        let reconnect_future = player_actor
+            .send(CreatePlayer {
+                viewport_size,
+                token: None,
+                profile: Profile {
+                    nickname: "first".to_string(),
+                    skin: 1,
+                },
+            })
+            .and_then(|first_result: CreatePlayerResult| {
+                player_actor
+                    .send(CreatePlayer {
+                        viewport_size: Coordinates { x: 500, y: 500 },
+                        token: Some(first_result.token),
+                        profile: Profile::default(),
+                    })
+                    .map(move |second_result| (first_result, second_result))
+            })
+            .and_then(|(first_result, second_result)| {
+                assert_eq!(first_result.id, second_result.id);
+                assert_eq!(first_result.token, second_result.token);
+
+                player_actor
+                    .send(CreatePlayer {
+                        viewport_size,
+                        token: Some(Uuid::new_v4()),
+                        profile: Profile::default(),
+                    })
+                    .map(move |third_result| (first_result, third_result))
+            })
+            .and_then(|(first_result, third_result)| {
+                assert_ne!(first_result.id, third_result.id);
+                player_actor.send(GetState).map(move |result| (first_result, result))
+            })
+            .map(|(first_result, result): (CreatePlayerResult, Players)| {
+                assert_eq!(result.players_count, 2);
+                assert_eq!(
+                    result.players.get(&first_result.id).unwrap().viewport_size,
+                    Coordinates { x: 500, y: 500 }
+                );
+                // Reconnecting doesn't change how the player shows up to the others
+                assert_eq!(result.players.get(&first_result.id).unwrap().profile.nickname, "first");
+            });
+
+        system.block_on(reconnect_future).expect("System error");
+    }
+
+    #[test]
+    fn test_players_actor_split_and_merge() {
+        let mut system = System::new("players_split");
+        let mut initial_players = HashMap::new();
+        let player_id = Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").expect("Couldn't parse player id");
+        let small_player_id =
+            Uuid::parse_str("78a40100-4dc3-46e4-8a91-00e0316586e4").expect("Couldn't parse small player id");
+
+        initial_players.insert(
+            player_id,
+            Player::at(100, Coordinates { x: 1000, y: 1000 }, Coordinates { x: 1000, y: 1000 }),
+        );
+        initial_players.insert(
+            small_player_id,
+            Player::at(
+                MIN_SPLIT_SIZE - 1,
+                Coordinates { x: 5000, y: 5000 },
+                Coordinates { x: 1000, y: 1000 },
+            ),
+        );
+
+        let player_actor = Arc::new(Players::new(initial_players, 2).start());
+
+        let split_future = player_actor
+            .send(SplitPlayer {
+                id: small_player_id,
+                direction: Delta { x: 1, y: 0 },
+            })
+            .and_then(|result: SplitPlayerResult| {
+                assert!(result.0.is_none());
+
+                player_actor.send(SplitPlayer {
+                    id: player_id,
+                    direction: Delta { x: 1, y: 0 },
+                })
+            })
+            .and_then(|result: SplitPlayerResult| {
+                let correction = result.0.expect("Player didn't split");
+
+                assert_eq!(correction.cells.len(), 2);
+                assert_eq!(correction.cells[0].size, 50);
+                assert_eq!(correction.cells[1].size, 50);
+
+                player_actor.send(SplitPlayer {
+                    id: player_id,
+                    direction: Delta { x: 0, y: -3 },
+                })
+            })
+            .and_then(|result: SplitPlayerResult| {
+                let correction = result.0.expect("Player didn't split");
+                let size: u32 = correction.cells.iter().map(|cell| cell.size).sum();
+
+                assert_eq!(correction.cells.len(), 4);
+                assert_eq!(size, 100);
+
+                player_actor.send(MovePlayer {
+                    id: player_id,
+                    moved: Delta { x: 0, y: 0 },
+                    size: 100,
+                    dots_consumed: 0,
+                    dots_mass: 0,
+                })
+            })
+            .and_then(|_result: MovePlayerResult| player_actor.send(GetState))
+            .map(move |result: Players| {
+                let player = result.players.get(&player_id).unwrap();
+                let first_cell = player.cells[0];
+                let second_cell = player.cells[1];
+
+                // The split cells still can't merge, and the new one flew away from the original one
+                assert_eq!(player.cells.len(), 4);
+                assert!(first_cell.merge_at > Instant::now());
+                assert!(second_cell.coordinates.x > first_cell.coordinates.x);
+                assert!(second_cell.velocity.x < SPLIT_SPEED);
+            });
+
+        system.block_on(split_future).expect("System error");
+    }
+
+    #[test]
+    fn test_player_merge_cells() {
+        let mut player = Player::at(20, Coordinates { x: 100, y: 100 }, Coordinates { x: 1000, y: 1000 });
+        player.cells.push(Cell::new(1, 30, Coordinates { x: 110, y: 110 }));
+        player.cells.push(Cell::new(2, 30, Coordinates { x: 500, y: 500 }));
+
+        let now = Instant::now();
+        player.cells[0].merge_at = now + MERGE_COOLDOWN;
+
+        assert!(!player.merge_cells(now));
+        assert_eq!(player.cells.len(), 3);
+
+        player.cells[0].merge_at = now;
+
+        assert!(player.merge_cells(now));
+        assert_eq!(player.cells.len(), 2);
+        assert_eq!(player.cells[0].size, 50);
+        assert_eq!(player.size(), 80);
+    }
+
+    #[test]
+    fn test_players_actor_eject_mass() {
+        let mut system = System::new("players_eject");
+        let mut initial_players = HashMap::new();
+        let player_id = Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").expect("Couldn't parse player id");
+        let small_player_id =
+            Uuid::parse_str("78a40100-4dc3-46e4-8a91-00e0316586e4").expect("Couldn't parse small player id");
+
+        initial_players.insert(
+            player_id,
+            Player::at(40, Coordinates { x: 1000, y: 1000 }, Coordinates { x: 1000, y: 1000 }),
+        );
+        initial_players.insert(
+            small_player_id,
+            Player::at(
+                MIN_EJECT_SIZE - 1,
+                Coordinates { x: 5000, y: 5000 },
+                Coordinates { x: 1000, y: 1000 },
+            ),
+        );
+
+        let player_actor = Arc::new(Players::new(initial_players, 2).start());
+
+        let eject_future = player_actor
+            .send(EjectMass {
+                id: small_player_id,
+                direction: Delta { x: 1, y: 0 },
+            })
+            .and_then(|result: EjectMassResult| {
+                assert!(result.correction.is_none());
+                assert!(result.ejected.is_empty());
+
+                player_actor.send(EjectMass {
+                    id: player_id,
+                    direction: Delta { x: -1, y: 0 },
+                })
+            })
+            .map(|result: EjectMassResult| {
+                let correction = result.correction.expect("Player didn't eject");
+
+                assert_eq!(correction.cells[0].size, 40 - EJECT_MASS);
+                assert_eq!(result.ejected.len(), 1);
+
+                // The dot starts outside of the cell, on the side it was shot towards
+                let dot = result.ejected[0];
+                assert_eq!(dot.mass, EJECT_MASS);
+                assert!(dot.coordinates.x + DOT_SIZE <= 1000);
+                assert!(dot.velocity.x < 0.0);
+                assert_eq!(dot.velocity.y, 0.0);
+            });
+
+        system.block_on(eject_future).expect("System error");
+    }
+
+    #[test]
+    fn test_players_actor_pop_cell() {
+        let mut system = System::new("players_pop");
+        let mut initial_players = HashMap::new();
+        let player_id = Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").expect("Couldn't parse player id");
+
+        initial_players.insert(
+            player_id,
+            Player::at(200, Coordinates { x: 1000, y: 1000 }, Coordinates { x: 1000, y: 1000 }),
+        );
+
+        let player_actor = Arc::new(Players::new(initial_players, 1).start());
+
+        let pop_future = player_actor
+            .send(PopCell {
+                id: player_id,
+                cell: 1,
+                mass: 100,
+            })
+            .and_then(|result: SplitPlayerResult| {
+                assert!(result.0.is_none());
+
+                player_actor.send(PopCell {
+                    id: player_id,
+                    cell: 0,
+                    mass: 100,
+                })
+            })
+            .map(|result: SplitPlayerResult| {
+                let correction = result.0.expect("Cell didn't pop");
+                let size: u32 = correction.cells.iter().map(|cell| cell.size).sum();
+
+                assert_eq!(correction.cells.len(), 15);
+                assert_eq!(size, 300);
+                assert!(correction.cells.iter().all(|cell| cell.size == 20));
+            });
+
+        system.block_on(pop_future).expect("System error");
+    }
 }
@@ -0,0 +1,436 @@
+use actix::dev::MessageResponse;
+use actix::prelude::*;
+use futures::future;
+use log::{info, warn};
+use serde::Serialize;
+use uuid::Uuid;
+
+use std::collections::HashMap;
+
+use crate::actors::world::{self, ConnectedPlayer, KickTarget, World};
+use crate::config::Config;
+use crate::consts::{HEALTH_CHECK_TIMEOUT, MAX_ROOM_NAME_LENGTH};
+
+// ********
+// Messages
+// ********
+/// Finds a world of the room with the given name that isn't full, creating one if there is none.
+/// Fails when a world is needed but there are already max_worlds
+#[derive(Message)]
+#[rtype(result = "Result<JoinRoomResult, String>")]
+pub struct JoinRoom(pub String);
+
+/// Sent when a connection to the world ends
+#[derive(Message)]
+pub struct LeaveRoom(pub Addr<World>);
+
+/// Answers right away, to check that the rooms actor still handles its messages
+#[derive(Message)]
+pub struct Ping;
+
+/// Pings every world, answering with the amount of worlds or with the ones that didn't answer in time
+#[derive(Message)]
+#[rtype(result = "Result<usize, String>")]
+pub struct CheckHealth;
+
+/// The players connected to every world, by room
+#[derive(Message)]
+#[rtype(result = "Result<Vec<WorldPlayers>, MailboxError>")]
+pub struct ListPlayers;
+
+/// Kicks the matching players from every world. Answers with the ids of the players kicked
+#[derive(Message)]
+#[rtype(result = "Result<Vec<Uuid>, MailboxError>")]
+pub struct KickPlayers {
+    pub target: KickTarget,
+    pub reason: String,
+}
+
+// ****************
+// Messages results
+// ****************
+#[derive(MessageResponse, Debug)]
+pub struct JoinRoomResult(pub Addr<World>);
+
+// ********
+// Types
+// ********
+#[derive(Debug, Serialize)]
+pub struct WorldPlayers {
+    pub room: String,
+    // Starting from 1, in the order the worlds of the room were created
+    pub world: usize,
+    pub players: Vec<ConnectedPlayer>,
+}
+
+/// One of the worlds backing a room
+#[derive(Debug, Clone)]
+pub struct WorldSlot {
+    pub world: Addr<World>,
+    // Connections to the world
+    pub players: usize,
+    // Pending teardown of the world once the last player left
+    pub teardown: Option<SpawnHandle>,
+}
+
+/// Every room name can be backed by many worlds, a new one being created whenever the others are full
+#[derive(MessageResponse, Debug, Clone)]
+pub struct Rooms {
+    pub rooms: HashMap<String, Vec<WorldSlot>>,
+    // Given to the worlds of the rooms
+    config: Config,
+}
+
+impl Rooms {
+    pub fn from_config(config: Config) -> Self {
+        Rooms {
+            rooms: HashMap::new(),
+            config,
+        }
+    }
+}
+
+impl Rooms {
+    fn tear_down(&mut self, address: &Addr<World>) {
+        for (name, worlds) in self.rooms.iter_mut() {
+            if worlds.iter().any(|slot| slot.world == *address) {
+                info!("room={} Tearing down an empty world", name);
+            }
+            worlds.retain(|slot| slot.world != *address);
+        }
+        self.rooms.retain(|_name, worlds| !worlds.is_empty());
+
+        address.do_send(world::Shutdown);
+    }
+}
+
+/// Room names are made of letters, digits, dashes and underscores
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_ROOM_NAME_LENGTH
+        && name
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || character == '-' || character == '_')
+}
+
+impl Actor for Rooms {
+    type Context = Context<Self>;
+}
+
+// ********
+// Handlers
+// ********
+impl Handler<JoinRoom> for Rooms {
+    type Result = Result<JoinRoomResult, String>;
+
+    fn handle(&mut self, message: JoinRoom, context: &mut Context<Self>) -> Self::Result {
+        let config = &self.config;
+        let worlds_amount: usize = self.rooms.values().map(Vec::len).sum();
+        let has_room_left = self
+            .rooms
+            .get(&message.0)
+            .is_some_and(|worlds| worlds.iter().any(|slot| slot.players < config.max_players_per_world));
+
+        if !has_room_left && worlds_amount >= config.max_worlds {
+            warn!(
+                "room={} Refused a player, there are already {} worlds",
+                message.0, worlds_amount
+            );
+            return Err("The server is full".to_string());
+        }
+
+        let worlds = self.rooms.entry(message.0.clone()).or_default();
+
+        let slot = match worlds
+            .iter_mut()
+            .position(|slot| slot.players < config.max_players_per_world)
+        {
+            Some(index) => &mut worlds[index],
+            None => {
+                info!("room={} Creating world {}", message.0, worlds.len() + 1);
+                worlds.push(WorldSlot {
+                    world: World::from_config(config).start(),
+                    players: 0,
+                    teardown: None,
+                });
+                worlds.last_mut().expect("A world was just added")
+            }
+        };
+
+        if let Some(handle) = slot.teardown.take() {
+            context.cancel_future(handle);
+        }
+        slot.players += 1;
+
+        Ok(JoinRoomResult(slot.world.clone()))
+    }
+}
+
+impl Handler<LeaveRoom> for Rooms {
+    type Result = ();
+
+    fn handle(&mut self, message: LeaveRoom, context: &mut Context<Self>) {
+        let slot = self
+            .rooms
+            .values_mut()
+            .flat_map(|worlds| worlds.iter_mut())
+            .find(|slot| slot.world == message.0);
+
+        if let Some(slot) = slot {
+            slot.players = slot.players.saturating_sub(1);
+
+            // Disconnected players can still come back to their cells for a while
+            if slot.players == 0 {
+                let address = message.0;
+                slot.teardown = Some(context.run_later(
+                    self.config.intervals.room_teardown_delay,
+                    move |actor, _context| {
+                        actor.tear_down(&address);
+                    },
+                ));
+            }
+        }
+    }
+}
+
+impl Handler<Ping> for Rooms {
+    type Result = ();
+
+    fn handle(&mut self, _message: Ping, _context: &mut Context<Self>) {}
+}
+
+impl Handler<CheckHealth> for Rooms {
+    type Result = ResponseFuture<usize, String>;
+
+    fn handle(&mut self, _message: CheckHealth, _context: &mut Context<Self>) -> Self::Result {
+        let checks: Vec<_> = self
+            .rooms
+            .iter()
+            .flat_map(|(name, worlds)| worlds.iter().enumerate().map(move |(index, slot)| (name, index, slot)))
+            .map(|(name, index, slot)| {
+                let name = name.clone();
+
+                // The world waits for its own actors first
+                slot.world
+                    .send(world::Ping)
+                    .timeout(HEALTH_CHECK_TIMEOUT * 2)
+                    .then(move |result| {
+                        let result = match result {
+                            Ok(Ok(())) => Ok(()),
+                            Ok(Err(error)) | Err(error) => {
+                                warn!("room={} World {} failed its health check: {}", name, index + 1, error);
+                                Err(format!("room={} world {}: {}", name, index + 1, error))
+                            }
+                        };
+
+                        Ok::<_, String>(result)
+                    })
+            })
+            .collect();
+        let worlds_count = checks.len();
+
+        let check_health_future = future::join_all(checks).and_then(move |results| {
+            let failures: Vec<String> = results.into_iter().filter_map(Result::err).collect();
+
+            if failures.is_empty() {
+                Ok(worlds_count)
+            } else {
+                Err(failures.join(", "))
+            }
+        });
+
+        Box::new(check_health_future)
+    }
+}
+
+impl Handler<ListPlayers> for Rooms {
+    type Result = ResponseFuture<Vec<WorldPlayers>, MailboxError>;
+
+    fn handle(&mut self, _message: ListPlayers, _context: &mut Context<Self>) -> Self::Result {
+        let lists: Vec<_> = self
+            .rooms
+            .iter()
+            .flat_map(|(name, worlds)| worlds.iter().enumerate().map(move |(index, slot)| (name, index, slot)))
+            .map(|(name, index, slot)| {
+                let name = name.clone();
+
+                slot.world
+                    .send(world::ListPlayers)
+                    .and_then(|result| result)
+                    .map(move |players| WorldPlayers {
+                        room: name,
+                        world: index + 1,
+                        players,
+                    })
+            })
+            .collect();
+
+        Box::new(future::join_all(lists))
+    }
+}
+
+impl Handler<KickPlayers> for Rooms {
+    type Result = ResponseFuture<Vec<Uuid>, MailboxError>;
+
+    fn handle(&mut self, message: KickPlayers, _context: &mut Context<Self>) -> Self::Result {
+        let kicks: Vec<_> = self
+            .rooms
+            .values()
+            .flatten()
+            .map(|slot| {
+                slot.world
+                    .send(world::KickPlayers {
+                        target: message.target,
+                        reason: message.reason.clone(),
+                    })
+                    .and_then(|result| result)
+            })
+            .collect();
+
+        Box::new(future::join_all(kicks).map(|kicked| kicked.into_iter().flatten().collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::Future;
+    use std::sync::Arc;
+
+    #[derive(Message)]
+    #[rtype(result = "Rooms")]
+    struct GetState;
+
+    impl Handler<GetState> for Rooms {
+        type Result = Rooms;
+
+        fn handle(&mut self, _message: GetState, _context: &mut Context<Self>) -> Rooms {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_rooms_actor_join_and_leave() {
+        let mut system = System::new("rooms_join");
+        let config = Config {
+            max_players_per_world: 2,
+            ..Config::default()
+        };
+        let rooms_actor = Arc::new(Rooms::from_config(config).start());
+
+        let join_future = rooms_actor
+            .send(JoinRoom("first".to_string()))
+            .map(Result::unwrap)
+            .join(rooms_actor.send(JoinRoom("first".to_string())).map(Result::unwrap))
+            .and_then(|(first, second)| {
+                // The first world is full
+                assert_eq!(first.0, second.0);
+                rooms_actor
+                    .send(JoinRoom("first".to_string()))
+                    .map(Result::unwrap)
+                    .join(rooms_actor.send(JoinRoom("second".to_string())).map(Result::unwrap))
+                    .map(move |(third, other)| (first, third, other))
+            })
+            .and_then(|(first, third, other)| {
+                assert_ne!(first.0, third.0);
+                assert_ne!(first.0, other.0);
+
+                rooms_actor.do_send(LeaveRoom(first.0.clone()));
+                rooms_actor.do_send(LeaveRoom(third.0.clone()));
+                rooms_actor.send(GetState).map(move |result| (first, result))
+            })
+            .and_then(|(first, result)| {
+                let worlds = &result.rooms["first"];
+
+                assert_eq!(result.rooms.len(), 2);
+                assert_eq!(worlds.len(), 2);
+                assert_eq!(worlds[0].players, 1);
+                assert!(worlds[0].teardown.is_none());
+                assert_eq!(worlds[1].players, 0);
+                assert!(worlds[1].teardown.is_some());
+
+                // Players go to the world that has room left before a new one is created
+                rooms_actor
+                    .send(JoinRoom("first".to_string()))
+                    .map(Result::unwrap)
+                    .map(move |joined| (first, joined))
+            })
+            .and_then(|(first, joined)| {
+                assert_eq!(first.0, joined.0);
+                rooms_actor.send(GetState)
+            })
+            .map(|result| {
+                assert_eq!(result.rooms["first"][0].players, 2);
+            });
+
+        system.block_on(join_future).expect("System error");
+    }
+
+    #[test]
+    fn test_rooms_actor_max_worlds() {
+        let mut system = System::new("rooms_max_worlds");
+        let config = Config {
+            max_players_per_world: 1,
+            max_worlds: 2,
+            ..Config::default()
+        };
+        let rooms_actor = Arc::new(Rooms::from_config(config).start());
+
+        let join_future = rooms_actor
+            .send(JoinRoom("first".to_string()))
+            .join(rooms_actor.send(JoinRoom("second".to_string())))
+            .and_then(|(first, second)| {
+                assert!(first.is_ok());
+                assert!(second.is_ok());
+
+                // Both need a new world
+                rooms_actor
+                    .send(JoinRoom("first".to_string()))
+                    .join(rooms_actor.send(JoinRoom("third".to_string())))
+            })
+            .and_then(|(first, third)| {
+                assert!(first.is_err());
+                assert!(third.is_err());
+
+                rooms_actor.send(GetState)
+            })
+            .map(|result| {
+                assert_eq!(result.rooms.len(), 2);
+                assert_eq!(result.rooms["first"].len(), 1);
+            });
+
+        system.block_on(join_future).expect("System error");
+    }
+
+    #[test]
+    fn test_rooms_actor_check_health() {
+        let mut system = System::new("rooms_health");
+        let rooms_actor = Arc::new(Rooms::from_config(Config::default()).start());
+
+        let health_future = rooms_actor
+            .send(CheckHealth)
+            .and_then(|result| {
+                assert_eq!(result, Ok(0));
+
+                rooms_actor
+                    .send(JoinRoom("first".to_string()))
+                    .map(Result::unwrap)
+                    .join(rooms_actor.send(JoinRoom("second".to_string())).map(Result::unwrap))
+            })
+            .and_then(|_worlds| rooms_actor.send(CheckHealth))
+            .map(|result| {
+                assert_eq!(result, Ok(2));
+            });
+
+        system.block_on(health_future).expect("System error");
+    }
+
+    #[test]
+    fn test_rooms_name() {
+        assert!(is_valid_name("default"));
+        assert!(is_valid_name("team_1-eu"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("../admin"));
+        assert!(!is_valid_name(&"a".repeat(MAX_ROOM_NAME_LENGTH + 1)));
+    }
+}
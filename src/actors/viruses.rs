@@ -0,0 +1,458 @@
+use actix::dev::MessageResponse;
+use actix::prelude::*;
+use serde::Serialize;
+use uuid::Uuid;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::actors::dots::Dot;
+use crate::actors::players::CellData;
+use crate::actors::world::{Coordinates, Ping, Shutdown, Velocity};
+use crate::config::Config;
+use crate::consts::{DOT_SIZE, EJECT_DECELERATION, VIRUS_FEED_LIMIT, VIRUS_SHOOT_DISTANCE, VIRUS_SIZE};
+use crate::utils::{apply_delta, generate_coordinates};
+
+// ********
+// Messages
+// ********
+#[derive(Message)]
+#[rtype(result = "GetVirusesResult")]
+pub struct GetViruses {
+    pub coordinates: Coordinates,
+    pub viewport_size: Coordinates,
+}
+
+/// Finds a virus touched by one of the cells bigger than it, and removes it from the world
+#[derive(Message)]
+#[rtype(result = "HitVirusResult")]
+pub struct HitVirus {
+    pub cells: Vec<CellData>,
+}
+
+/// Puts back a virus that was hit by a cell that couldn't pop, e.g. because its player died meanwhile
+#[derive(Message)]
+pub struct RestoreVirus(pub VirusHit);
+
+/// Feeds the viruses on the way of the ejected dots
+#[derive(Message)]
+#[rtype(result = "FeedVirusesResult")]
+pub struct FeedViruses(pub Vec<Dot>);
+
+// ****************
+// Messages results
+// ****************
+#[derive(MessageResponse, Message, Debug)]
+pub struct GetVirusesResult {
+    pub viruses: HashMap<Uuid, VirusData>,
+}
+
+#[derive(MessageResponse, Debug)]
+pub struct HitVirusResult(pub Option<VirusHit>);
+
+/// The dots that didn't reach any virus
+#[derive(MessageResponse, Debug)]
+pub struct FeedVirusesResult(pub Vec<Dot>);
+
+// ********
+// Types
+// ********
+/// The cell that hit a virus and the mass it gains from it, with the virus to put back if the cell can't pop
+#[derive(Debug, Copy, Clone)]
+pub struct VirusHit {
+    pub cell: u32,
+    pub mass: u32,
+    pub id: Uuid,
+    pub virus: Virus,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Virus {
+    pub coordinates: Coordinates,
+    // Mass ejected into the virus since it last shot
+    pub fed: u32,
+}
+
+impl Virus {
+    fn new(coordinates: Coordinates) -> Self {
+        Virus { coordinates, fed: 0 }
+    }
+
+    fn center(&self) -> (f64, f64) {
+        (
+            f64::from(self.coordinates.x + VIRUS_SIZE / 2),
+            f64::from(self.coordinates.y + VIRUS_SIZE / 2),
+        )
+    }
+
+    // Only cells bigger than the virus pop on it, the others pass under it
+    fn is_hit_by(&self, cell: &CellData) -> bool {
+        cell.size > VIRUS_SIZE
+            && self.coordinates.x < cell.coordinates.x + cell.size
+            && self.coordinates.x + VIRUS_SIZE > cell.coordinates.x
+            && self.coordinates.y < cell.coordinates.y + cell.size
+            && self.coordinates.y + VIRUS_SIZE > cell.coordinates.y
+    }
+
+    // Whether the dot crosses the virus before it settles
+    fn is_on_the_way(&self, dot: &Dot) -> bool {
+        let speed = dot.velocity.x.hypot(dot.velocity.y);
+        if speed == 0.0 {
+            return false;
+        }
+
+        let travel = speed * speed / (2.0 * EJECT_DECELERATION);
+        let start = (
+            f64::from(dot.coordinates.x + DOT_SIZE / 2),
+            f64::from(dot.coordinates.y + DOT_SIZE / 2),
+        );
+        let direction = (dot.velocity.x / speed, dot.velocity.y / speed);
+        let (center_x, center_y) = self.center();
+
+        // Distance from the center of the virus to the closest point of the dot trajectory
+        let along = ((center_x - start.0) * direction.0 + (center_y - start.1) * direction.1)
+            .max(0.0)
+            .min(travel);
+        let closest = (start.0 + direction.0 * along, start.1 + direction.1 * along);
+
+        (center_x - closest.0).hypot(center_y - closest.1) < f64::from(VIRUS_SIZE / 2 + DOT_SIZE / 2)
+    }
+
+    // The new virus lands VIRUS_SHOOT_DISTANCE away, in the direction of the last feeding
+    fn shoot(&mut self, velocity: Velocity, world_size: Coordinates) -> Virus {
+        let speed = velocity.x.hypot(velocity.y);
+        let distance = f64::from(VIRUS_SHOOT_DISTANCE) / speed;
+        let (x, _clamped) = apply_delta(
+            self.coordinates.x,
+            (velocity.x * distance) as i32,
+            world_size.x - VIRUS_SIZE,
+        );
+        let (y, _clamped) = apply_delta(
+            self.coordinates.y,
+            (velocity.y * distance) as i32,
+            world_size.y - VIRUS_SIZE,
+        );
+
+        self.fed = 0;
+        Virus::new(Coordinates { x, y })
+    }
+
+    fn data(&self) -> VirusData {
+        VirusData {
+            coordinates: self.coordinates,
+            size: VIRUS_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct VirusData {
+    pub coordinates: Coordinates,
+    pub size: u32,
+}
+
+#[derive(MessageResponse, Clone, Debug)]
+pub struct Viruses {
+    pub viruses: HashMap<Uuid, Virus>,
+    pub max_viruses_amount: u32,
+    world_size: Coordinates,
+    create_interval: Duration,
+}
+
+impl Viruses {
+    fn generate_viruses(&mut self) {
+        while (self.viruses.len() as u32) < self.max_viruses_amount {
+            self.viruses
+                .insert(Uuid::new_v4(), Virus::new(generate_coordinates(self.world_size)));
+        }
+    }
+
+    fn run_viruses_creation_interval(&self, context: &mut Context<Self>) {
+        context.run_interval(self.create_interval, |actor, _context| {
+            actor.generate_viruses();
+        });
+    }
+}
+
+#[cfg(test)]
+impl Viruses {
+    fn new(viruses: HashMap<Uuid, Virus>) -> Self {
+        let max_viruses_amount = viruses.len() as u32;
+
+        Viruses {
+            viruses,
+            max_viruses_amount,
+            ..Viruses::default()
+        }
+    }
+}
+
+impl Viruses {
+    pub fn from_config(config: &Config) -> Self {
+        Viruses {
+            viruses: HashMap::new(),
+            max_viruses_amount: config.max_viruses_amount,
+            world_size: config.world_size,
+            create_interval: config.intervals.viruses_create,
+        }
+    }
+}
+
+impl Default for Viruses {
+    fn default() -> Self {
+        Viruses::from_config(&Config::default())
+    }
+}
+
+impl Actor for Viruses {
+    type Context = Context<Self>;
+
+    fn started(&mut self, context: &mut Context<Self>) {
+        self.generate_viruses();
+        self.run_viruses_creation_interval(context);
+    }
+}
+
+impl Handler<GetViruses> for Viruses {
+    type Result = GetVirusesResult;
+
+    fn handle(&mut self, message: GetViruses, _context: &mut Context<Self>) -> Self::Result {
+        let center = message.coordinates;
+        let min_x = (center.x).saturating_sub(message.viewport_size.x / 2);
+        let max_x = center.x + (message.viewport_size.x / 2);
+        let min_y = (center.y).saturating_sub(message.viewport_size.y / 2);
+        let max_y = center.y + (message.viewport_size.y / 2);
+
+        let viruses = self
+            .viruses
+            .iter()
+            .filter(|(_id, virus)| {
+                virus.coordinates.x + VIRUS_SIZE >= min_x
+                    && virus.coordinates.x < max_x
+                    && virus.coordinates.y + VIRUS_SIZE >= min_y
+                    && virus.coordinates.y < max_y
+            })
+            .map(|(id, virus)| (*id, virus.data()))
+            .collect();
+
+        GetVirusesResult { viruses }
+    }
+}
+
+impl Handler<HitVirus> for Viruses {
+    type Result = HitVirusResult;
+
+    fn handle(&mut self, message: HitVirus, _context: &mut Context<Self>) -> Self::Result {
+        for cell in message.cells.iter() {
+            let hit = self
+                .viruses
+                .iter()
+                .find(|(_id, virus)| virus.is_hit_by(cell))
+                .map(|(id, _virus)| *id);
+
+            if let Some((id, virus)) = hit.and_then(|id| self.viruses.remove_entry(&id)) {
+                return HitVirusResult(Some(VirusHit {
+                    cell: cell.id,
+                    mass: VIRUS_SIZE,
+                    id,
+                    virus,
+                }));
+            }
+        }
+
+        HitVirusResult(None)
+    }
+}
+
+impl Handler<RestoreVirus> for Viruses {
+    type Result = ();
+
+    fn handle(&mut self, message: RestoreVirus, _context: &mut Context<Self>) {
+        self.viruses.insert(message.0.id, message.0.virus);
+    }
+}
+
+impl Handler<FeedViruses> for Viruses {
+    type Result = FeedVirusesResult;
+
+    fn handle(&mut self, message: FeedViruses, _context: &mut Context<Self>) -> Self::Result {
+        let mut remaining = Vec::new();
+        let mut shot = Vec::new();
+
+        for dot in message.0 {
+            let fed = self.viruses.values_mut().find(|virus| virus.is_on_the_way(&dot));
+
+            match fed {
+                Some(virus) => {
+                    virus.fed += dot.mass;
+                    if virus.fed >= VIRUS_FEED_LIMIT {
+                        shot.push(virus.shoot(dot.velocity, self.world_size));
+                    }
+                }
+                None => remaining.push(dot),
+            }
+        }
+
+        for virus in shot {
+            self.viruses.insert(Uuid::new_v4(), virus);
+        }
+
+        FeedVirusesResult(remaining)
+    }
+}
+
+impl Handler<Shutdown> for Viruses {
+    type Result = ();
+
+    fn handle(&mut self, _message: Shutdown, context: &mut Context<Self>) {
+        context.stop();
+    }
+}
+
+impl Handler<Ping> for Viruses {
+    type Result = Result<(), MailboxError>;
+
+    fn handle(&mut self, _message: Ping, _context: &mut Context<Self>) -> Self::Result {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::{EJECT_MASS, EJECT_SPEED};
+    use futures::Future;
+    use std::sync::Arc;
+
+    #[derive(Message)]
+    #[rtype(result = "Viruses")]
+    struct GetState;
+
+    impl Handler<GetState> for Viruses {
+        type Result = Viruses;
+
+        fn handle(&mut self, _message: GetState, _context: &mut Context<Self>) -> Viruses {
+            self.clone()
+        }
+    }
+
+    fn ejected_dot(coordinates: Coordinates) -> Dot {
+        Dot {
+            coordinates,
+            mass: EJECT_MASS,
+            velocity: Velocity { x: EJECT_SPEED, y: 0.0 },
+        }
+    }
+
+    #[test]
+    fn test_viruses_actor_hit_virus() {
+        let mut system = System::new("viruses_hit");
+        let mut initial_viruses = HashMap::new();
+        let virus_id = Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").expect("Couldn't parse virus id");
+
+        initial_viruses.insert(virus_id, Virus::new(Coordinates { x: 1000, y: 1000 }));
+
+        let viruses_actor = Arc::new(Viruses::new(initial_viruses).start());
+
+        let hit_virus_future = viruses_actor
+            .send(HitVirus {
+                cells: vec![CellData {
+                    id: 0,
+                    coordinates: Coordinates { x: 1020, y: 1020 },
+                    size: VIRUS_SIZE - 10,
+                }],
+            })
+            .and_then(|result: HitVirusResult| {
+                // Smaller cells pass under the virus
+                assert!(result.0.is_none());
+
+                viruses_actor.send(HitVirus {
+                    cells: vec![
+                        CellData {
+                            id: 0,
+                            coordinates: Coordinates { x: 5000, y: 5000 },
+                            size: VIRUS_SIZE + 10,
+                        },
+                        CellData {
+                            id: 1,
+                            coordinates: Coordinates { x: 950, y: 950 },
+                            size: VIRUS_SIZE + 10,
+                        },
+                    ],
+                })
+            })
+            .and_then(|result: HitVirusResult| {
+                let hit = result.0.expect("The virus wasn't hit");
+                assert_eq!((hit.cell, hit.mass, hit.id), (1, VIRUS_SIZE, virus_id));
+
+                viruses_actor.send(GetState).map(move |result: Viruses| (result, hit))
+            })
+            .and_then(|(result, hit)| {
+                assert!(result.viruses.is_empty());
+
+                // Put back when the cell couldn't pop
+                viruses_actor.do_send(RestoreVirus(hit));
+                viruses_actor.send(GetState)
+            })
+            .map(move |result: Viruses| {
+                assert!(result.viruses.contains_key(&virus_id));
+            });
+
+        system.block_on(hit_virus_future).expect("System error");
+    }
+
+    #[test]
+    fn test_viruses_actor_feed_viruses() {
+        let mut system = System::new("viruses_feed");
+        let mut initial_viruses = HashMap::new();
+        let virus_id = Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").expect("Couldn't parse virus id");
+
+        initial_viruses.insert(virus_id, Virus::new(Coordinates { x: 1000, y: 1000 }));
+
+        let viruses_actor = Arc::new(Viruses::new(initial_viruses).start());
+        let feeds = VIRUS_FEED_LIMIT / EJECT_MASS;
+
+        let feed_viruses_future = viruses_actor
+            .send(FeedViruses(vec![
+                ejected_dot(Coordinates { x: 900, y: 1045 }),
+                ejected_dot(Coordinates { x: 900, y: 3000 }),
+            ]))
+            .and_then(|result: FeedVirusesResult| {
+                // Only the dot that missed the virus is left
+                assert_eq!(result.0.len(), 1);
+                assert_eq!(result.0[0].coordinates, Coordinates { x: 900, y: 3000 });
+
+                viruses_actor.send(GetState)
+            })
+            .and_then(|result: Viruses| {
+                assert_eq!(result.viruses.get(&virus_id).unwrap().fed, EJECT_MASS);
+
+                let dots = (1..feeds)
+                    .map(|_| ejected_dot(Coordinates { x: 900, y: 1045 }))
+                    .collect();
+                viruses_actor.send(FeedViruses(dots))
+            })
+            .and_then(|result: FeedVirusesResult| {
+                assert!(result.0.is_empty());
+
+                viruses_actor.send(GetState)
+            })
+            .map(move |result: Viruses| {
+                let virus = result.viruses.get(&virus_id).unwrap();
+
+                assert_eq!(result.viruses.len(), 2);
+                assert_eq!(virus.fed, 0);
+
+                let (_id, new_virus) = result.viruses.iter().find(|(id, _virus)| **id != virus_id).unwrap();
+                assert_eq!(
+                    new_virus.coordinates,
+                    Coordinates {
+                        x: 1000 + VIRUS_SHOOT_DISTANCE,
+                        y: 1000
+                    }
+                );
+            });
+
+        system.block_on(feed_viruses_future).expect("System error");
+    }
+}
@@ -1,81 +1,392 @@
 use actix;
 use actix::prelude::*;
 use futures::future;
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::actors::dots::Dots;
+use crate::actors::viruses::Viruses;
 use crate::actors::ws::Ws;
-use crate::actors::{dots, players, ws};
-use crate::consts::{DOTS_SEND_INTERVAL, PLAYERS_SEND_INTERVAL, WORLD_X_SIZE, WORLD_Y_SIZE};
-use crate::server_messages;
+use crate::actors::{dots, players, viruses, ws};
+use crate::client_messages::Delta;
+use crate::config::Config;
+use crate::consts::{
+    CHAT_PROXIMITY_RADIUS, HEALTH_CHECK_TIMEOUT, MAX_CHAT_MESSAGE_LENGTH, PROTOCOL_VERSION, VIRUS_SIZE,
+};
+use crate::metrics;
+use crate::server_messages::{self, ErrorCode, ErrorResponse};
+
+// ********
+// Messages
+// ********
+/// Stops an actor of a world that is torn down
+#[derive(Message)]
+pub struct Shutdown;
+
+/// Health check of an actor of a world. The world only answers once its own actors did
+#[derive(Message)]
+#[rtype(result = "Result<(), MailboxError>")]
+pub struct Ping;
+
+/// The players connected to the world, for the admin API
+#[derive(Message)]
+#[rtype(result = "Result<Vec<ConnectedPlayer>, MailboxError>")]
+pub struct ListPlayers;
+
+/// Closes the connections of the matching players and removes their players right away, so that their tokens
+/// can't be used to take them back. Answers with the ids of the players kicked
+#[derive(Message)]
+#[rtype(result = "Result<Vec<Uuid>, MailboxError>")]
+pub struct KickPlayers {
+    pub target: KickTarget,
+    pub reason: String,
+}
 
 // ********
 // Types
 // ********
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum KickTarget {
+    Player(Uuid),
+    Ip(IpAddr),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectedPlayer {
+    pub id: Uuid,
+    pub coordinates: Coordinates,
+    pub size: u32,
+    pub ip: Option<IpAddr>,
+    pub token: Uuid,
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Coordinates {
     pub x: u32,
     pub y: u32,
 }
 
+/// In world units per second
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Velocity {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Velocity {
+    // Velocity of the given speed towards delta, or towards the right when delta is null
+    pub fn towards(delta: Delta, speed: f64) -> Self {
+        let length = f64::from(delta.x).hypot(f64::from(delta.y));
+
+        if length > 0.0 {
+            Velocity {
+                x: f64::from(delta.x) / length * speed,
+                y: f64::from(delta.y) / length * speed,
+            }
+        } else {
+            Velocity { x: speed, y: 0.0 }
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.x == 0.0 && self.y == 0.0
+    }
+
+    // Distance travelled during elapsed, slowing down by deceleration (in world units per second squared)
+    pub fn glide(&mut self, elapsed: Duration, deceleration: f64) -> (i32, i32) {
+        let elapsed = elapsed.as_secs_f64();
+        let moved = ((self.x * elapsed) as i32, (self.y * elapsed) as i32);
+
+        let speed = self.x.hypot(self.y);
+        if speed > 0.0 {
+            let ratio = (speed - deceleration * elapsed).max(0.0) / speed;
+            self.x *= ratio;
+            self.y *= ratio;
+        }
+
+        moved
+    }
+}
+
 #[derive(Debug)]
 pub struct World {
     players_connected: HashMap<Addr<Ws>, Uuid>,
+    // Address of the client of each connected player, when known
+    players_ips: HashMap<Uuid, IpAddr>,
+    // Players whose connection dropped, with the pending removal of their cell
+    players_disconnected: HashMap<Uuid, SpawnHandle>,
     players_actor: Arc<Addr<players::Players>>,
     dots_actor: Arc<Addr<Dots>>,
+    viruses_actor: Arc<Addr<Viruses>>,
+    config: Config,
 }
 
 impl World {
+    fn player_address(&self, id: &Uuid) -> Option<Addr<Ws>> {
+        self.players_connected
+            .iter()
+            .find(|(_address, player_id)| *player_id == id)
+            .map(|(address, _player_id)| address.clone())
+    }
+
+    // At the end of the grace period, or right away when kicked.
+    // Kept when its client took it back since it disconnected
+    fn remove_player(&mut self, id: Uuid, disconnected_at: Instant, context: &mut Context<Self>) {
+        self.players_disconnected.remove(&id);
+
+        let remove_player_future = self
+            .players_actor
+            .send(players::RemoveDisconnectedPlayer { id, disconnected_at })
+            .into_actor(self)
+            .map(move |result: Option<players::RemovePlayerResult>, actor, _context| {
+                let result = match result {
+                    Some(result) => result,
+                    None => {
+                        info!("player={} Reconnected while being removed", id);
+                        return;
+                    }
+                };
+                info!("player={} Removed from the world", id);
+                actor.dots_actor.do_send(dots::ForgetPlayer(id));
+
+                for nearby_id in result.nearby {
+                    if let Some(address) = actor.player_address(&nearby_id) {
+                        address.do_send(server_messages::PlayerLeftResponse { id });
+                    }
+                }
+            })
+            .map_err(move |error, _actor, _context| {
+                error!("player={} Couldn't remove the player: {}", id, error);
+            });
+
+        context.spawn(remove_player_future);
+    }
+
+    fn resolve_collision(&self, collision: players::CollisionData, context: &mut Context<Self>) {
+        let resolve_collision_future = self
+            .players_actor
+            .send(players::ConsumeCell(collision))
+            .into_actor(self)
+            .map(move |result: players::ConsumeCellResult, actor, _context| {
+                let winner_size = match result {
+                    players::ConsumeCellResult::NotApplied => return,
+                    players::ConsumeCellResult::Consumed {
+                        winner_size,
+                        loser_cells,
+                    } => {
+                        if let Some(address) = actor.player_address(&collision.lose_id) {
+                            address.do_send(server_messages::CorrectionResponse { cells: loser_cells });
+                        }
+
+                        winner_size
+                    }
+                    players::ConsumeCellResult::Died {
+                        winner_size,
+                        final_size,
+                        stats,
+                    } => {
+                        info!("player={} Eaten by {}", collision.lose_id, collision.win_id);
+
+                        if let Some(address) = actor.player_address(&collision.lose_id) {
+                            address.do_send(server_messages::EatenResponse { by: collision.win_id });
+                            address.do_send(server_messages::PlayerDiedResponse {
+                                size: final_size,
+                                max_size: stats.max_size,
+                                dots_eaten: stats.dots_eaten,
+                                players_eaten: stats.players_eaten,
+                                time_alive: stats.spawned_at.elapsed().as_secs(),
+                            });
+                        }
+
+                        // The dead player keeps watching the game from the eyes of the winner until it respawns
+                        actor.players_actor.do_send(players::SpectatePlayer {
+                            id: collision.lose_id,
+                            target: collision.win_id,
+                        });
+
+                        winner_size
+                    }
+                };
+
+                if let Some(address) = actor.player_address(&collision.win_id) {
+                    address.do_send(server_messages::ConsumedResponse {
+                        id: collision.lose_id,
+                        size: winner_size,
+                    });
+                }
+            })
+            .map_err(move |error, _actor, _context| {
+                error!(
+                    "player={} Couldn't resolve the collision with {}: {}",
+                    collision.win_id, collision.lose_id, error
+                );
+            });
+
+        context.spawn(resolve_collision_future);
+    }
+
+    // Cells bigger than a virus that touch it pop into many pieces
+    fn hit_virus(&self, id: Uuid, cells: Vec<players::CellData>, address: Addr<Ws>, context: &mut Context<Self>) {
+        if cells.iter().all(|cell| cell.size <= VIRUS_SIZE) {
+            return;
+        }
+
+        let players_actor = self.players_actor.clone();
+        let viruses_actor = self.viruses_actor.clone();
+
+        let hit_virus_future = self
+            .viruses_actor
+            .send(viruses::HitVirus { cells })
+            .and_then(move |result: viruses::HitVirusResult| match result.0 {
+                Some(hit) => future::Either::A(
+                    players_actor
+                        .send(players::PopCell {
+                            id,
+                            cell: hit.cell,
+                            mass: hit.mass,
+                        })
+                        .map(move |result| {
+                            // The virus is only gone once a cell popped on it
+                            if result.0.is_none() {
+                                viruses_actor.do_send(viruses::RestoreVirus(hit));
+                            }
+                            result.0
+                        }),
+                ),
+                None => future::Either::B(future::ok(None)),
+            })
+            .map(move |correction: Option<players::Correction>| {
+                if let Some(correction) = correction {
+                    address.do_send(server_messages::CorrectionResponse {
+                        cells: correction.cells,
+                    });
+                }
+            })
+            .map_err(move |error| {
+                error!("player={} Couldn't hit the virus: {}", id, error);
+            });
+
+        context.spawn(hit_virus_future.into_actor(self));
+    }
+
     fn run_dots_interval(&self, context: &mut Context<Self>) {
-        context.run_interval(DOTS_SEND_INTERVAL, |actor, _context| {
+        context.run_interval(self.config.intervals.dots_send, |actor, _context| {
+            let timer = metrics::BROADCAST_SECONDS.with_label_values(&["dots"]).start_timer();
+            let mut futures = Vec::with_capacity(actor.players_connected.len());
+
             for (address, id) in actor.players_connected.iter() {
                 let players_actor = actor.players_actor.clone();
                 let dots_actor = actor.dots_actor.clone();
+                let viruses_actor = actor.viruses_actor.clone();
 
-                let player_id = id.clone();
+                let player_id = *id;
                 let player_address = address.clone();
 
                 let get_player_dots_future = players_actor
                     .send(players::GetPlayer(player_id))
-                    .and_then(move |result: players::Player| {
-                        dots_actor.send(dots::GetDots {
-                            id: player_id,
-                            coordinates: result.coordinates,
-                            viewport_size: result.viewport_size,
-                        })
+                    .and_then(move |result: players::GetPlayerResult| {
+                        let viewport = match result.0 {
+                            Some(viewport) => viewport,
+                            None => return future::Either::A(future::ok(None)),
+                        };
+
+                        future::Either::B(
+                            dots_actor
+                                .send(dots::GetDots {
+                                    id: player_id,
+                                    coordinates: viewport.coordinates,
+                                    viewport_size: viewport.viewport_size,
+                                    full: false,
+                                })
+                                .join(viruses_actor.send(viruses::GetViruses {
+                                    coordinates: viewport.coordinates,
+                                    viewport_size: viewport.viewport_size,
+                                }))
+                                .map(Some),
+                        )
                     })
-                    .map(move |result| {
-                        player_address.do_send(result);
+                    .map(move |updates| {
+                        if let Some((dots, viruses)) = updates {
+                            player_address.do_send(dots);
+                            player_address.do_send(viruses);
+                        }
                     })
-                    .map_err(|error| {
-                        println!("{}", error);
+                    .map_err(move |error| {
+                        error!("player={} Couldn't send the dots: {}", player_id, error);
                     });
 
-                Arbiter::spawn(get_player_dots_future);
+                futures.push(get_player_dots_future);
             }
+
+            Arbiter::spawn(future::join_all(futures).then(move |_result| {
+                timer.observe_duration();
+                Ok(())
+            }));
         });
     }
 
     fn run_players_interval(&self, context: &mut Context<Self>) {
-        context.run_interval(PLAYERS_SEND_INTERVAL, |actor, _context| {
-            for id in actor.players_connected.values() {
+        context.run_interval(self.config.intervals.players_send, |actor, _context| {
+            let timer = metrics::BROADCAST_SECONDS.with_label_values(&["players"]).start_timer();
+            let mut futures = Vec::with_capacity(actor.players_connected.len());
+
+            for (address, id) in actor.players_connected.iter() {
                 let players_actor = actor.players_actor.clone();
+                let player_address = address.clone();
+                let player_id = *id;
 
                 let get_players_in_viewport_future = players_actor
-                    .send(players::GetPlayersInViewport(*id))
-                    .map(|result: players::GetPlayersInViewportResult| {
-                        println!("{:?}", result);
+                    .send(players::GetPlayersInViewport(player_id))
+                    .map(move |result: players::GetPlayersInViewportResult| {
+                        player_address.do_send(result);
                     })
-                    .map_err(|error| {
-                        println!("{}", error);
+                    .map_err(move |error| {
+                        error!("player={} Couldn't send the players: {}", player_id, error);
                     });
 
-                Arbiter::spawn(get_players_in_viewport_future);
+                futures.push(get_players_in_viewport_future);
+            }
+
+            Arbiter::spawn(future::join_all(futures).then(move |_result| {
+                timer.observe_duration();
+                Ok(())
+            }));
+        });
+    }
+
+    fn run_leaderboard_interval(&self, context: &mut Context<Self>) {
+        context.run_interval(self.config.intervals.leaderboard_send, |actor, context| {
+            if actor.players_connected.is_empty() {
+                return;
             }
+
+            let timer = metrics::BROADCAST_SECONDS
+                .with_label_values(&["leaderboard"])
+                .start_timer();
+            let leaderboard_future = actor
+                .players_actor
+                .send(players::GetLeaderboard)
+                .into_actor(actor)
+                .map(|result: players::GetLeaderboardResult, actor, _context| {
+                    for (address, id) in actor.players_connected.iter() {
+                        address.do_send(server_messages::LeaderboardResponse {
+                            leaders: result.leaders.clone(),
+                            rank: result.ranks.get(id).copied(),
+                        });
+                    }
+                    timer.observe_duration();
+                })
+                .map_err(|error, _actor, _context| {
+                    error!("Couldn't send the leaderboard: {}", error);
+                });
+
+            context.spawn(leaderboard_future);
         });
     }
 }
@@ -86,22 +397,132 @@ impl Actor for World {
     fn started(&mut self, context: &mut Self::Context) {
         self.run_dots_interval(context);
         self.run_players_interval(context);
+        self.run_leaderboard_interval(context);
+    }
+
+    fn stopped(&mut self, _context: &mut Self::Context) {
+        metrics::CONNECTED_PLAYERS.sub(self.players_connected.len() as i64);
+
+        self.players_actor.do_send(Shutdown);
+        self.dots_actor.do_send(Shutdown);
+        self.viruses_actor.do_send(Shutdown);
     }
 }
 
-impl Default for World {
-    fn default() -> Self {
+impl World {
+    pub fn from_config(config: &Config) -> Self {
         World {
             players_connected: HashMap::new(),
-            players_actor: Arc::new(players::Players::default().start()),
-            dots_actor: Arc::new(Dots::default().start()),
+            players_ips: HashMap::new(),
+            players_disconnected: HashMap::new(),
+            players_actor: Arc::new(players::Players::from_config(config).start()),
+            dots_actor: Arc::new(Dots::from_config(config).start()),
+            viruses_actor: Arc::new(Viruses::from_config(config).start()),
+            config: config.clone(),
         }
     }
 }
 
+impl Default for World {
+    fn default() -> Self {
+        World::from_config(&Config::default())
+    }
+}
+
 // ********
 // Handlers
 // ********
+impl Handler<Shutdown> for World {
+    type Result = ();
+
+    fn handle(&mut self, _message: Shutdown, context: &mut Context<Self>) {
+        context.stop();
+    }
+}
+
+impl Handler<Ping> for World {
+    type Result = ResponseFuture<(), MailboxError>;
+
+    fn handle(&mut self, _message: Ping, _context: &mut Context<Self>) -> Self::Result {
+        let ping_future = self
+            .players_actor
+            .send(Ping)
+            .timeout(HEALTH_CHECK_TIMEOUT)
+            .join3(
+                self.dots_actor.send(Ping).timeout(HEALTH_CHECK_TIMEOUT),
+                self.viruses_actor.send(Ping).timeout(HEALTH_CHECK_TIMEOUT),
+            )
+            .and_then(|(players, dots, viruses)| players.and(dots).and(viruses));
+
+        Box::new(ping_future)
+    }
+}
+
+impl Handler<ListPlayers> for World {
+    type Result = ResponseActFuture<Self, Vec<ConnectedPlayer>, MailboxError>;
+
+    fn handle(&mut self, _message: ListPlayers, _context: &mut Context<Self>) -> Self::Result {
+        let ids = self.players_connected.values().copied().collect();
+
+        let list_players_future = self
+            .players_actor
+            .send(players::GetPlayersSummaries(ids))
+            .into_actor(self)
+            .map(|result: players::GetPlayersSummariesResult, actor, _context| {
+                result
+                    .0
+                    .into_iter()
+                    .map(|summary| ConnectedPlayer {
+                        id: summary.id,
+                        coordinates: summary.coordinates,
+                        size: summary.size,
+                        ip: actor.players_ips.get(&summary.id).copied(),
+                        token: summary.token,
+                    })
+                    .collect()
+            });
+
+        Box::new(list_players_future)
+    }
+}
+
+impl Handler<KickPlayers> for World {
+    type Result = Result<Vec<Uuid>, MailboxError>;
+
+    fn handle(&mut self, message: KickPlayers, context: &mut Context<Self>) -> Self::Result {
+        let kicked: Vec<Uuid> = self
+            .players_connected
+            .values()
+            .copied()
+            .filter(|id| match message.target {
+                KickTarget::Player(target) => *id == target,
+                KickTarget::Ip(ip) => self.players_ips.get(id) == Some(&ip),
+            })
+            .collect();
+
+        // Without a grace period, the disconnection that follows finds nothing left to wait for
+        let connected = self.players_connected.len();
+        self.players_connected.retain(|address, id| {
+            if !kicked.contains(id) {
+                return true;
+            }
+            info!("player={} Kicked: {}", id, message.reason);
+            address.do_send(ws::Kick {
+                reason: message.reason.clone(),
+            });
+            false
+        });
+        metrics::CONNECTED_PLAYERS.sub((connected - self.players_connected.len()) as i64);
+
+        for id in kicked.iter() {
+            self.players_ips.remove(id);
+            self.remove_player(*id, Instant::now(), context);
+        }
+
+        Ok(kicked)
+    }
+}
+
 impl Handler<ws::ConnectPlayer> for World {
     type Result = ResponseActFuture<Self, server_messages::CreateResponse, ()>;
 
@@ -109,33 +530,76 @@ impl Handler<ws::ConnectPlayer> for World {
         let player_address = message.address.clone();
         let players_actor = self.players_actor.clone();
         let dots_actor = self.dots_actor.clone();
+        let viruses_actor = self.viruses_actor.clone();
+        let world_size = self.config.world_size;
+        let session = message.session;
 
         let connect_player_future = players_actor
-            .send(players::CreatePlayer(message.request.viewport_size))
+            .send(players::CreatePlayer {
+                viewport_size: message.request.viewport_size,
+                token: message.request.token,
+                profile: players::Profile {
+                    nickname: message.request.nickname.clone(),
+                    skin: message.request.skin,
+                },
+            })
             .and_then(move |new_player| {
-                dots_actor.send(dots::GetDots {
-                    id: new_player.id,
-                    coordinates: new_player.coordinates,
-                    viewport_size: message.request.viewport_size,
-                })
+                let session = ws::Session {
+                    player: Some(new_player.id),
+                    ..session
+                };
+                debug!("{} Player created at {:?}", session, new_player.coordinates);
+
+                dots_actor
+                    .send(dots::GetDots {
+                        id: new_player.id,
+                        coordinates: new_player.coordinates,
+                        viewport_size: message.request.viewport_size,
+                        full: true,
+                    })
+                    .join(viruses_actor.send(viruses::GetViruses {
+                        coordinates: new_player.coordinates,
+                        viewport_size: message.request.viewport_size,
+                    }))
+                    .map(move |(dots, viruses)| (new_player, dots, viruses))
             })
-            .and_then(|result| {
+            .and_then(move |(new_player, dots, viruses)| {
+                debug!(
+                    "player={} Sending {} dots and {} viruses",
+                    new_player.id,
+                    dots.dots_added.len(),
+                    viruses.viruses.len()
+                );
+
                 future::ok(server_messages::CreateResponse {
-                    id: result.player_id,
-                    world_size: Coordinates {
-                        x: WORLD_X_SIZE,
-                        y: WORLD_Y_SIZE,
-                    },
-                    dots: result.dots,
+                    protocol_version: PROTOCOL_VERSION,
+                    id: dots.player_id,
+                    token: new_player.token,
+                    world_size,
+                    dots: dots.dots_added,
+                    viruses: viruses.viruses,
                 })
             })
             .into_actor(self)
-            .map(move |result, actor, _context| {
+            .map(move |result, actor, context| {
+                // The player reconnected before its cell was removed
+                if let Some(handle) = actor.players_disconnected.remove(&result.id) {
+                    info!("connection={} player={} Reconnected", session.connection, result.id);
+                    context.cancel_future(handle);
+                }
+
+                let connected = actor.players_connected.len();
+                actor.players_connected.retain(|_address, id| *id != result.id);
                 actor.players_connected.insert(player_address, result.id);
+                match session.ip {
+                    Some(ip) => actor.players_ips.insert(result.id, ip),
+                    None => actor.players_ips.remove(&result.id),
+                };
+                metrics::CONNECTED_PLAYERS.add(actor.players_connected.len() as i64 - connected as i64);
                 result
             })
-            .map_err(|error, _actor, _context| {
-                println!("{}", error);
+            .map_err(move |error, _actor, _context| {
+                error!("{} Couldn't connect the player: {}", session, error);
             });
 
         Box::new(connect_player_future)
@@ -145,38 +609,436 @@ impl Handler<ws::ConnectPlayer> for World {
 impl Handler<ws::DisconnectPlayer> for World {
     type Result = ();
 
-    fn handle(&mut self, message: ws::DisconnectPlayer, _context: &mut Context<Self>) {
-        self.players_connected.remove(&message.address);
+    fn handle(&mut self, message: ws::DisconnectPlayer, context: &mut Context<Self>) {
+        if let Some(id) = self.players_connected.remove(&message.address) {
+            self.players_ips.remove(&id);
+            debug!("{} Waiting for the client to reconnect", message.session);
+            metrics::CONNECTED_PLAYERS.dec();
+
+            // Give the client a chance to reconnect before its cell is removed from the world
+            let disconnected_at = Instant::now();
+            let handle = context.run_later(self.config.intervals.disconnect_grace_period, move |actor, context| {
+                actor.remove_player(id, disconnected_at, context);
+            });
+
+            self.players_disconnected.insert(id, handle);
+        }
     }
 }
 
 impl Handler<ws::MovePlayer> for World {
     type Result = ();
 
-    fn handle(&mut self, message: ws::MovePlayer, _context: &mut Context<Self>) {
+    fn handle(&mut self, message: ws::MovePlayer, context: &mut Context<Self>) {
         let players_actor = self.players_actor.clone();
         let dots_actor = self.dots_actor.clone();
+        let player_address = message.address;
+        let (id, size, moved) = (message.request.id, message.request.size, message.request.moved);
+        let session = message.session;
+        let dots_consumed = message.request.dots_consumed;
+
+        // Only the dots within reach of the cells of the player count towards its size
+        let move_player_future = players_actor
+            .send(players::GetReach(id))
+            .and_then(move |reach: players::GetReachResult| {
+                dots_actor.send(dots::DeleteDots {
+                    ids: dots_consumed,
+                    areas: reach.0,
+                })
+            })
+            .and_then(move |deleted: dots::DeleteDotsResult| {
+                players_actor
+                    .send(players::MovePlayer {
+                        id,
+                        size,
+                        moved,
+                        dots_consumed: deleted.count,
+                        dots_mass: deleted.mass,
+                    })
+                    .map(move |result| (result, deleted.removed))
+            })
+            .into_actor(self)
+            .map(move |(result, removed), actor, context| {
+                // The other players that could see the consumed dots are told right away
+                for (player_id, dots_removed) in removed {
+                    if player_id == id {
+                        continue;
+                    }
+                    if let Some(address) = actor.player_address(&player_id) {
+                        address.do_send(server_messages::DotsResponse {
+                            dots_added: HashMap::new(),
+                            dots_removed,
+                        });
+                    }
+                }
+
+                if let Some(correction) = result.correction {
+                    debug!("{} Move corrected", session);
+                    player_address.do_send(server_messages::CorrectionResponse {
+                        cells: correction.cells,
+                    });
+                }
+
+                for collision in result.collisions {
+                    actor.resolve_collision(collision, context);
+                }
+
+                actor.hit_virus(id, result.cells, player_address, context);
+            })
+            .map_err(move |error, _actor, _context| {
+                error!("{} Couldn't move the player: {}", session, error);
+            });
+
+        context.spawn(move_player_future);
+    }
+}
+
+impl Handler<ws::SplitPlayer> for World {
+    type Result = ();
+
+    fn handle(&mut self, message: ws::SplitPlayer, context: &mut Context<Self>) {
+        let player_address = message.address;
+
+        let session = message.session;
+
+        // A client can only split its own player
+        if self.players_connected.get(&player_address) != Some(&message.request.id) {
+            warn!("{} Tried to split player {}", session, message.request.id);
+            return;
+        }
+
+        let split_player_future = self
+            .players_actor
+            .send(players::SplitPlayer {
+                id: message.request.id,
+                direction: message.request.split,
+            })
+            .map(move |result: players::SplitPlayerResult| {
+                if let Some(correction) = result.0 {
+                    player_address.do_send(server_messages::CorrectionResponse {
+                        cells: correction.cells,
+                    });
+                }
+            })
+            .map_err(move |error| {
+                error!("{} Couldn't split the player: {}", session, error);
+            });
+
+        context.spawn(split_player_future.into_actor(self));
+    }
+}
+
+impl Handler<ws::EjectMass> for World {
+    type Result = ();
+
+    fn handle(&mut self, message: ws::EjectMass, context: &mut Context<Self>) {
+        let player_address = message.address;
+        let dots_actor = self.dots_actor.clone();
+        let viruses_actor = self.viruses_actor.clone();
+
+        let session = message.session;
 
-        if message.request.dots_consumed.len() > 0 {
-            dots_actor.do_send(dots::DeleteDots(message.request.dots_consumed));
+        // A client can only eject mass from its own player
+        if self.players_connected.get(&player_address) != Some(&message.request.id) {
+            warn!("{} Tried to eject mass from player {}", session, message.request.id);
+            return;
         }
 
-        let move_player_future = players_actor
-            .send(players::MovePlayer {
+        let eject_mass_future = self
+            .players_actor
+            .send(players::EjectMass {
                 id: message.request.id,
-                size: message.request.size,
-                moved: message.request.moved,
+                direction: message.request.eject,
             })
-            .map(|result: players::MovePlayerResult| match result.0 {
-                Some(collision_data) => {
-                    println!("{:?}", collision_data);
+            .and_then(move |result: players::EjectMassResult| match result.correction {
+                Some(correction) => {
+                    player_address.do_send(server_messages::CorrectionResponse {
+                        cells: correction.cells,
+                    });
+
+                    // The ejected dots that run into a virus feed it instead of settling
+                    future::Either::A(
+                        viruses_actor
+                            .send(viruses::FeedViruses(result.ejected))
+                            .map(move |result| {
+                                dots_actor.do_send(dots::AddDots(result.0));
+                            }),
+                    )
                 }
-                None => {}
+                None => future::Either::B(future::ok(())),
             })
-            .map_err(|error| {
-                println!("{}", error);
+            .map_err(move |error| {
+                error!("{} Couldn't eject mass: {}", session, error);
+            });
+
+        context.spawn(eject_mass_future.into_actor(self));
+    }
+}
+
+impl Handler<ws::RespawnPlayer> for World {
+    type Result = ();
+
+    fn handle(&mut self, message: ws::RespawnPlayer, context: &mut Context<Self>) {
+        let player_address = message.address;
+        let dots_actor = self.dots_actor.clone();
+        let viruses_actor = self.viruses_actor.clone();
+        let viewport_size = message.request.viewport_size;
+        let world_size = self.config.world_size;
+
+        let session = message.session;
+
+        // A client can only respawn its own player
+        if self.players_connected.get(&player_address) != Some(&message.request.id) {
+            warn!("{} Tried to respawn player {}", session, message.request.id);
+            return;
+        }
+
+        let respawn_player_future = self
+            .players_actor
+            .send(players::RespawnPlayer {
+                id: message.request.id,
+                viewport_size,
+            })
+            .and_then(move |result: players::RespawnPlayerResult| match result.0 {
+                Some(player) => future::Either::A(
+                    dots_actor
+                        .send(dots::GetDots {
+                            id: player.id,
+                            coordinates: player.coordinates,
+                            viewport_size,
+                            full: true,
+                        })
+                        .join(viruses_actor.send(viruses::GetViruses {
+                            coordinates: player.coordinates,
+                            viewport_size,
+                        }))
+                        .map(move |(dots, viruses)| Some((player, dots, viruses))),
+                ),
+                None => future::Either::B(future::ok(None)),
+            })
+            .map(
+                move |result: Option<(
+                    players::CreatePlayerResult,
+                    dots::GetDotsResult,
+                    viruses::GetVirusesResult,
+                )>| {
+                    if let Some((player, dots, viruses)) = result {
+                        info!("{} Respawned", session);
+                        player_address.do_send(server_messages::CreateResponse {
+                            protocol_version: PROTOCOL_VERSION,
+                            id: dots.player_id,
+                            token: player.token,
+                            world_size,
+                            dots: dots.dots_added,
+                            viruses: viruses.viruses,
+                        });
+                    }
+                },
+            )
+            .map_err(move |error| {
+                error!("{} Couldn't respawn the player: {}", session, error);
+            });
+
+        context.spawn(respawn_player_future.into_actor(self));
+    }
+}
+
+impl Handler<ws::SendChat> for World {
+    type Result = ();
+
+    fn handle(&mut self, message: ws::SendChat, context: &mut Context<Self>) {
+        let player_address = message.address;
+        let id = message.request.id;
+        let session = message.session;
+
+        // A client can only chat as its own player
+        if self.players_connected.get(&player_address) != Some(&id) {
+            warn!("{} Tried to chat as player {}", session, id);
+            return;
+        }
+
+        let text = message.request.message.trim();
+        if text.is_empty() || text.chars().count() > MAX_CHAT_MESSAGE_LENGTH {
+            player_address.do_send(ErrorResponse::new(
+                ErrorCode::InvalidChatMessage,
+                &format!(
+                    "Chat messages have to be 1 to {} characters long",
+                    MAX_CHAT_MESSAGE_LENGTH
+                ),
+            ));
+            return;
+        }
+
+        let text = text.to_string();
+        let nearby_future = if message.request.proximity {
+            future::Either::A(
+                self.players_actor
+                    .send(players::GetNearbyPlayers {
+                        id,
+                        radius: CHAT_PROXIMITY_RADIUS,
+                    })
+                    .map(|result| Some(result.0)),
+            )
+        } else {
+            future::Either::B(future::ok(None))
+        };
+
+        let chat_future = self
+            .players_actor
+            .send(players::GetProfile(id))
+            .join(nearby_future)
+            .into_actor(self)
+            .map(move |(profile, nearby), actor, _context| {
+                let chat_message = server_messages::ChatMessageResponse {
+                    id,
+                    profile: profile.0.unwrap_or_default(),
+                    message: text,
+                    proximity: nearby.is_some(),
+                };
+
+                match nearby {
+                    Some(nearby) => {
+                        for nearby_id in nearby {
+                            if let Some(address) = actor.player_address(&nearby_id) {
+                                address.do_send(chat_message.clone());
+                            }
+                        }
+                        player_address.do_send(chat_message);
+                    }
+                    None => {
+                        for address in actor.players_connected.keys() {
+                            address.do_send(chat_message.clone());
+                        }
+                    }
+                }
+            })
+            .map_err(move |error, _actor, _context| {
+                error!("{} Couldn't send the chat message: {}", session, error);
+            });
+
+        context.spawn(chat_future);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::players::{Player, Players};
+    use crate::actors::ws::tests::{client_frame, connect};
+    use futures::Stream;
+
+    fn create(token: Uuid) -> String {
+        format!(
+            r#"{{"type": "create", "protocol_version": 1, "viewport_size": {{"x": 1000, "y": 1000}}, "token": "{}"}}"#,
+            token
+        )
+    }
+
+    // Reads messages until `count` of the given types arrived, handing back the stream so the client stays connected
+    fn wait_for<S>(
+        messages: S,
+        types: &'static [&'static str],
+        count: usize,
+    ) -> impl Future<Item = (Vec<serde_json::Value>, S), Error = ()>
+    where
+        S: Stream<Item = serde_json::Value, Error = ()>,
+    {
+        future::loop_fn((Vec::new(), messages), move |(mut received, messages)| {
+            messages
+                .into_future()
+                .map_err(|(error, _messages)| error)
+                .map(move |(message, messages)| {
+                    let message = message.expect("The connection was closed");
+
+                    if types.iter().any(|kind| message["type"] == *kind) {
+                        received.push(message);
+                    }
+
+                    if received.len() == count {
+                        future::Loop::Break((received, messages))
+                    } else {
+                        future::Loop::Continue((received, messages))
+                    }
+                })
+        })
+    }
+
+    #[test]
+    fn test_world_collision_notifications() {
+        let mut system = System::new("world_collision");
+        let config = Config::default();
+        let small_player = Player::at(20, Coordinates { x: 200, y: 200 }, Coordinates { x: 1000, y: 1000 });
+        let big_player = Player::at(40, Coordinates { x: 250, y: 200 }, Coordinates { x: 1000, y: 1000 });
+        let (small_token, big_token) = (small_player.token, big_player.token);
+        let small_player_id = Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").unwrap();
+        let big_player_id = Uuid::parse_str("78a40100-4dc3-46e4-8a91-00e0316586e4").unwrap();
+        let initial_players = vec![(small_player_id, small_player), (big_player_id, big_player)]
+            .into_iter()
+            .collect();
+
+        let world_actor = World {
+            players_actor: Arc::new(Players::new(initial_players, 2).start()),
+            ..World::from_config(&config)
+        }
+        .start();
+
+        // Both clients take back their player with its token
+        let (small_sender, small_messages) = connect(world_actor.clone(), &config);
+        let (big_sender, big_messages) = connect(world_actor.clone(), &config);
+        small_sender.unbounded_send(client_frame(&create(small_token))).unwrap();
+
+        let collision_future = small_messages
+            .into_future()
+            .map_err(|(error, _messages)| error)
+            .and_then(move |(created, small_messages)| {
+                assert_eq!(created.unwrap()["id"], small_player_id.to_string());
+                big_sender.unbounded_send(client_frame(&create(big_token))).unwrap();
+
+                big_messages
+                    .into_future()
+                    .map_err(|(error, _messages)| error)
+                    .map(move |(created, big_messages)| (created, small_messages, big_messages, big_sender))
+            })
+            .and_then(move |(created, small_messages, big_messages, big_sender)| {
+                assert_eq!(created.unwrap()["id"], big_player_id.to_string());
+
+                // The big player moves over the small one
+                let move_request = format!(
+                    r#"{{"type": "move", "id": "{}", "size": 40, "moved": {{"x": -40, "y": 0}}, "dots_consumed": []}}"#,
+                    big_player_id
+                );
+                big_sender.unbounded_send(client_frame(&move_request)).unwrap();
+
+                let loser_messages = wait_for(small_messages, &["eaten", "player_died"], 2);
+                let winner_messages = wait_for(big_messages, &["consumed"], 1);
+
+                loser_messages
+                    .join(winner_messages)
+                    .map(move |messages| (messages, small_sender, big_sender))
+            })
+            .and_then(move |(messages, small_sender, big_sender)| {
+                let ((loser_messages, small_messages), (winner_messages, big_messages)) = messages;
+
+                assert_eq!(loser_messages[0]["type"], "eaten");
+                assert_eq!(loser_messages[0]["by"], big_player_id.to_string());
+                assert_eq!(loser_messages[1]["type"], "player_died");
+                assert_eq!(winner_messages[0]["id"], small_player_id.to_string());
+                assert_eq!(winner_messages[0]["size"], 60);
+
+                // Keep both connections open while listing the players
+                world_actor
+                    .send(ListPlayers)
+                    .map_err(|_error| ())
+                    .map(move |players| (players, (small_sender, small_messages), (big_sender, big_messages)))
+            })
+            .map(move |(players, _small_client, _big_client)| {
+                let connected: Vec<Uuid> = players.unwrap().iter().map(|player| player.id).collect();
+
+                // The loser stays connected, watching the winner until it respawns
+                assert_eq!(connected.len(), 2);
+                assert!(connected.contains(&small_player_id));
             });
 
-        Arbiter::spawn(move_player_future);
+        system.block_on(collision_future).expect("System error");
     }
 }
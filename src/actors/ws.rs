@@ -1,14 +1,24 @@
 use actix;
 use actix::prelude::*;
 use actix_web_actors::ws;
+use log::{debug, error, info, warn};
 use serde_json;
+use uuid::Uuid;
 
-use std::time::{Instant};
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Instant;
 
-use crate::actors::{dots, world};
-use crate::client_messages::{ClientRequests, CreateRequest, MoveRequest};
-use crate::consts::{CLIENT_TIMEOUT, PING_SEND_INTERVAL};
-use crate::server_messages;
+use crate::actors::{dots, players, rooms, viruses, world};
+use crate::client_messages::{
+    validate_viewport, ChatRequest, ClientRequests, CreateRequest, EjectRequest, Encoding, MoveRequest, RespawnRequest,
+    SplitRequest,
+};
+use crate::config::{Intervals, RateLimits};
+use crate::consts::{MAX_MERGED_MOVES, PROTOCOL_VERSION};
+use crate::metrics;
+use crate::rate_limit::RateLimiter;
+use crate::server_messages::{self, ErrorCode, ErrorResponse, ServerResponses};
 
 // ********
 // Messages
@@ -18,33 +28,363 @@ use crate::server_messages;
 pub struct ConnectPlayer {
     pub request: CreateRequest,
     pub address: Addr<Ws>,
+    pub session: Session,
 }
 
 #[derive(Message)]
 pub struct DisconnectPlayer {
     pub address: Addr<Ws>,
+    pub session: Session,
 }
 
 #[derive(Message)]
 pub struct MovePlayer {
     pub request: MoveRequest,
     pub address: Addr<Ws>,
+    pub session: Session,
+}
+
+#[derive(Message)]
+pub struct SplitPlayer {
+    pub request: SplitRequest,
+    pub address: Addr<Ws>,
+    pub session: Session,
+}
+
+#[derive(Message)]
+pub struct EjectMass {
+    pub request: EjectRequest,
+    pub address: Addr<Ws>,
+    pub session: Session,
+}
+
+#[derive(Message)]
+pub struct RespawnPlayer {
+    pub request: RespawnRequest,
+    pub address: Addr<Ws>,
+    pub session: Session,
+}
+
+#[derive(Message)]
+pub struct SendChat {
+    pub request: ChatRequest,
+    pub address: Addr<Ws>,
+    pub session: Session,
+}
+
+/// Sent by the world to close the connection of a player kicked by an admin
+#[derive(Message)]
+pub struct Kick {
+    pub reason: String,
 }
 
 // ********
 // Types
 // ********
+/// Identifies a connection, and the player it controls once created, in the logs
+#[derive(Debug, Copy, Clone)]
+pub struct Session {
+    pub connection: Uuid,
+    pub player: Option<Uuid>,
+    // Address of the client, shown to the admins
+    pub ip: Option<IpAddr>,
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.player {
+            Some(player) => write!(formatter, "connection={} player={}", self.connection, player),
+            None => write!(formatter, "connection={} player=-", self.connection),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Ws {
     world_actor: Addr<world::World>,
+    rooms_actor: Addr<rooms::Rooms>,
     ping_timestamp: Instant,
+    // Only ping_send and client_timeout are used by the connection
+    intervals: Intervals,
+    // Picked by the client in its create request
+    encoding: Encoding,
+    // The player is set once created
+    session: Session,
+    // A create request is being handled, so that a second one is refused
+    creating: bool,
+    rate_limiter: RateLimiter,
+    // Moves sent over the rate limit, merged until the limit lets them through
+    pending_move: Option<MoveRequest>,
+    merged_moves: u32,
+    pending_move_flush: Option<SpawnHandle>,
 }
 
 impl Ws {
-    pub fn new(world_actor: Addr<world::World>) -> Self {
+    pub fn new(
+        world_actor: Addr<world::World>,
+        rooms_actor: Addr<rooms::Rooms>,
+        ip: Option<IpAddr>,
+        rate_limits: &RateLimits,
+        intervals: &Intervals,
+    ) -> Self {
         Ws {
             world_actor,
+            rooms_actor,
             ping_timestamp: Instant::now(),
+            intervals: *intervals,
+            encoding: Encoding::Json,
+            session: Session {
+                connection: Uuid::new_v4(),
+                player: None,
+                ip,
+            },
+            creating: false,
+            rate_limiter: RateLimiter::new(rate_limits),
+            pending_move: None,
+            merged_moves: 0,
+            pending_move_flush: None,
+        }
+    }
+
+    fn send_error(&self, context: &mut ws::WebsocketContext<Self>, code: ErrorCode, message: &str) {
+        self.send(context, ServerResponses::Error(ErrorResponse::new(code, message)));
+    }
+
+    // Counts a message refused or invalid, and disconnects the client once it went over the abuse limit
+    fn record_violation(&mut self, context: &mut ws::WebsocketContext<Self>) -> bool {
+        if self.rate_limiter.record_violation(Instant::now()) {
+            return true;
+        }
+
+        warn!("{} Disconnected for sending too many messages", self.session);
+        self.close(context, ws::CloseCode::Policy, "Too many messages".to_string());
+        false
+    }
+
+    fn handle_payload(&mut self, request: Result<ClientRequests, String>, context: &mut ws::WebsocketContext<Self>) {
+        self.ping_timestamp = Instant::now();
+
+        match request {
+            Ok(request) => self.handle_request(request, context),
+            Err(error) => {
+                debug!("{} Malformed message: {}", self.session, error);
+                metrics::INVALID_MESSAGES.inc();
+                if self.record_violation(context) {
+                    self.send_error(context, ErrorCode::Malformed, &error);
+                }
+            }
+        }
+    }
+
+    fn send_move(&mut self, request: MoveRequest, context: &mut ws::WebsocketContext<Self>) {
+        self.merged_moves = 0;
+        self.world_actor.do_send(MovePlayer {
+            request,
+            address: context.address(),
+            session: self.session,
+        });
+    }
+
+    // Moves over the rate limit wait for the next token instead of being refused, merged into a single move
+    fn handle_move(&mut self, request: MoveRequest, context: &mut ws::WebsocketContext<Self>) {
+        let request = match self.pending_move.take() {
+            Some(pending) => {
+                self.merged_moves += 1;
+                pending.merge(request)
+            }
+            None => request,
+        };
+
+        // A pending move means that there is no token left until it is flushed
+        if self.pending_move_flush.is_none() && self.rate_limiter.try_take("move", Instant::now()) {
+            self.send_move(request, context);
+            return;
+        }
+
+        self.pending_move = Some(request);
+        metrics::RATE_LIMITED_MESSAGES.with_label_values(&["move"]).inc();
+        // Clients sending a bit faster than the limit are fine, only floods count as abuse
+        if self.merged_moves > MAX_MERGED_MOVES && !self.record_violation(context) {
+            return;
+        }
+        if self.pending_move_flush.is_none() {
+            self.schedule_move_flush(context);
+        }
+    }
+
+    fn schedule_move_flush(&mut self, context: &mut ws::WebsocketContext<Self>) {
+        let wait_time = self.rate_limiter.wait_time("move", Instant::now());
+
+        self.pending_move_flush = Some(context.run_later(wait_time, |actor, context| {
+            actor.pending_move_flush = None;
+
+            if !actor.rate_limiter.try_take("move", Instant::now()) {
+                actor.schedule_move_flush(context);
+            } else if let Some(pending) = actor.pending_move.take() {
+                actor.send_move(pending, context);
+            }
+        }));
+    }
+
+    fn send(&self, context: &mut ws::WebsocketContext<Self>, response: ServerResponses) {
+        let timer = metrics::SERIALIZATION_SECONDS.start_timer();
+
+        match self.encoding {
+            Encoding::Json => {
+                let result_json = serde_json::to_string(&response).expect("Couldn't serialize server response");
+                timer.observe_duration();
+                context.text(result_json);
+            }
+            Encoding::MessagePack => {
+                let result = rmp_serde::to_vec_named(&response).expect("Couldn't serialize server response");
+                timer.observe_duration();
+                context.binary(result);
+            }
+        }
+    }
+
+    fn close(&self, context: &mut ws::WebsocketContext<Self>, code: ws::CloseCode, description: String) {
+        context.close(Some(ws::CloseReason {
+            code,
+            description: Some(description),
+        }));
+        context.stop();
+    }
+
+    fn connect_player(&mut self, request: CreateRequest, context: &mut ws::WebsocketContext<Self>) {
+        let create_request_future = self
+            .world_actor
+            .send(ConnectPlayer {
+                request,
+                address: context.address(),
+                session: self.session,
+            })
+            .into_actor(self)
+            .map(move |result, actor, context| match result {
+                Ok(response) => {
+                    actor.creating = false;
+                    actor.session.player = Some(response.id);
+                    info!("{} Player joined", actor.session);
+                    actor.send(context, ServerResponses::Create(response));
+                }
+                Err(()) => {
+                    actor.creating = false;
+                    error!("{} Couldn't create the player", actor.session);
+                    actor.send_error(context, ErrorCode::Internal, "Couldn't create the player");
+                }
+            })
+            .map_err(|error, actor, context| {
+                actor.creating = false;
+                error!("{} Couldn't create the player: {}", actor.session, error);
+                actor.send_error(context, ErrorCode::Internal, "Couldn't create the player");
+            });
+
+        context.spawn(create_request_future);
+    }
+
+    fn handle_request(&mut self, request: ClientRequests, context: &mut ws::WebsocketContext<Self>) {
+        let kind = request.kind();
+        metrics::MESSAGES_RECEIVED.with_label_values(&[kind]).inc();
+
+        // Moves are limited once handled, so that the ones over the limit are merged instead of refused
+        let is_move = matches!(request, ClientRequests::Move(_));
+        if !is_move && !self.rate_limiter.try_take(kind, Instant::now()) {
+            debug!("{} Rate limited {} message", self.session, kind);
+            metrics::RATE_LIMITED_MESSAGES.with_label_values(&[kind]).inc();
+            if self.record_violation(context) {
+                self.send_error(context, ErrorCode::RateLimited, "Too many messages");
+            }
+            return;
+        }
+
+        if let Some(id) = request.player_id() {
+            match self.session.player {
+                None => {
+                    self.send_error(context, ErrorCode::NotCreatedYet, "Create a player first");
+                    return;
+                }
+                Some(player_id) if player_id != id => {
+                    self.send_error(context, ErrorCode::UnknownPlayer, &format!("Unknown player {}", id));
+                    return;
+                }
+                _ => (),
+            }
+        }
+
+        match request {
+            ClientRequests::Create(msg) => {
+                // Another player would be left behind in the world, without any connection
+                if self.session.player.is_some() || self.creating {
+                    debug!("{} Player already created", self.session);
+                    self.send_error(context, ErrorCode::AlreadyCreated, "The player was already created");
+                    return;
+                }
+                if msg.protocol_version != PROTOCOL_VERSION {
+                    warn!("{} Unsupported protocol version {}", self.session, msg.protocol_version);
+                    self.close(
+                        context,
+                        ws::CloseCode::Protocol,
+                        format!("Unsupported protocol version {}", msg.protocol_version),
+                    );
+                    return;
+                }
+                self.encoding = msg.encoding;
+
+                if let Err(error) = msg.validate_profile() {
+                    debug!("{} Invalid profile: {}", self.session, error);
+                    self.send_error(context, ErrorCode::InvalidProfile, &error);
+                    return;
+                }
+                if let Err(error) = validate_viewport(msg.viewport_size) {
+                    debug!("{} Invalid viewport: {}", self.session, error);
+                    self.send_error(context, ErrorCode::InvalidViewport, &error);
+                    return;
+                }
+                self.creating = true;
+
+                self.connect_player(msg, context);
+            }
+            ClientRequests::Move(msg) => self.handle_move(msg, context),
+            ClientRequests::Split(msg) => {
+                self.world_actor.do_send(SplitPlayer {
+                    request: msg,
+                    address: context.address(),
+                    session: self.session,
+                });
+            }
+            ClientRequests::Eject(msg) => {
+                self.world_actor.do_send(EjectMass {
+                    request: msg,
+                    address: context.address(),
+                    session: self.session,
+                });
+            }
+            ClientRequests::Respawn(msg) => {
+                if let Err(error) = validate_viewport(msg.viewport_size) {
+                    debug!("{} Invalid viewport: {}", self.session, error);
+                    self.send_error(context, ErrorCode::InvalidViewport, &error);
+                    return;
+                }
+                self.world_actor.do_send(RespawnPlayer {
+                    request: msg,
+                    address: context.address(),
+                    session: self.session,
+                });
+            }
+            ClientRequests::Chat(msg) => {
+                self.world_actor.do_send(SendChat {
+                    request: msg,
+                    address: context.address(),
+                    session: self.session,
+                });
+            }
+            ClientRequests::Invalid => {
+                debug!("{} Unknown message type", self.session);
+                metrics::INVALID_MESSAGES.inc();
+                if self.record_violation(context) {
+                    self.send_error(context, ErrorCode::Malformed, "Unknown message type");
+                }
+            }
         }
     }
 }
@@ -53,19 +393,27 @@ impl Actor for Ws {
     type Context = ws::WebsocketContext<Self>;
 
     fn started(&mut self, context: &mut Self::Context) {
-        context.run_interval(PING_SEND_INTERVAL, |actor, context| {
-            if Instant::now().duration_since(actor.ping_timestamp) > CLIENT_TIMEOUT {
-                actor.world_actor.do_send(DisconnectPlayer {
-                    address: context.address(),
-                });
+        info!("{} Connected", self.session);
 
+        context.run_interval(self.intervals.ping_send, |actor, context| {
+            if Instant::now().duration_since(actor.ping_timestamp) > actor.intervals.client_timeout {
+                info!("{} Timed out", actor.session);
                 context.stop();
-                return;
             }
 
             //            context.ping("");
         });
     }
+
+    fn stopped(&mut self, context: &mut Self::Context) {
+        info!("{} Disconnected", self.session);
+
+        self.world_actor.do_send(DisconnectPlayer {
+            address: context.address(),
+            session: self.session,
+        });
+        self.rooms_actor.do_send(rooms::LeaveRoom(self.world_actor.clone()));
+    }
 }
 
 // ********
@@ -82,52 +430,352 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for Ws {
                 self.ping_timestamp = Instant::now();
             }
             ws::Message::Text(payload) => {
-                self.ping_timestamp = Instant::now();
-                let message: ClientRequests = serde_json::from_str(&payload).unwrap_or(ClientRequests::Invalid);
-
-                match message {
-                    ClientRequests::Create(msg) => {
-                        let create_request_future = self
-                            .world_actor
-                            .send(ConnectPlayer {
-                                request: msg,
-                                address: context.address(),
-                            })
-                            .into_actor(self)
-                            .map(move |result, _actor, context| {
-                                let result_json =
-                                    serde_json::to_string(&result.unwrap()).expect("Couldn't parse CreateResponse");
-                                context.text(result_json);
-                            })
-                            .map_err(|error, _actor, _context| {
-                                println!("{}", error);
-                            });
-
-                        context.spawn(create_request_future);
-                    }
-                    ClientRequests::Move(msg) => {
-                        self.world_actor.do_send(MovePlayer {
-                            request: msg,
-                            address: context.address(),
-                        });
-                    }
-                    ClientRequests::Invalid => {
-                        println!("Invalid message");
-                    }
-                }
+                let request = serde_json::from_str(&payload).map_err(|error| error.to_string());
+
+                self.handle_payload(request, context);
+            }
+            ws::Message::Binary(payload) => {
+                let request = rmp_serde::from_slice(&payload).map_err(|error| error.to_string());
+
+                self.handle_payload(request, context);
+            }
+            ws::Message::Close(_reason) => {
+                context.stop();
             }
             _ => (),
         }
     }
 }
 
+impl Handler<Kick> for Ws {
+    type Result = ();
+
+    fn handle(&mut self, message: Kick, context: &mut Self::Context) {
+        self.close(context, ws::CloseCode::Policy, message.reason);
+    }
+}
+
 impl Handler<dots::GetDotsResult> for Ws {
     type Result = ();
 
     fn handle(&mut self, message: dots::GetDotsResult, context: &mut Self::Context) {
-        let result_json = serde_json::to_string(&server_messages::DotsResponse { dots: message.dots })
-            .expect("Couldn't parse DotsResponse");
+        if message.dots_added.is_empty() && message.dots_removed.is_empty() {
+            return;
+        }
+
+        self.send(
+            context,
+            ServerResponses::Dots(server_messages::DotsResponse {
+                dots_added: message.dots_added,
+                dots_removed: message.dots_removed,
+            }),
+        );
+    }
+}
+
+impl Handler<server_messages::DotsResponse> for Ws {
+    type Result = ();
+
+    fn handle(&mut self, message: server_messages::DotsResponse, context: &mut Self::Context) {
+        self.send(context, ServerResponses::Dots(message));
+    }
+}
+
+impl Handler<viruses::GetVirusesResult> for Ws {
+    type Result = ();
+
+    fn handle(&mut self, message: viruses::GetVirusesResult, context: &mut Self::Context) {
+        self.send(
+            context,
+            ServerResponses::Viruses(server_messages::VirusesResponse {
+                viruses: message.viruses,
+            }),
+        );
+    }
+}
+
+impl Handler<players::GetPlayersInViewportResult> for Ws {
+    type Result = ();
+
+    fn handle(&mut self, message: players::GetPlayersInViewportResult, context: &mut Self::Context) {
+        self.send(
+            context,
+            ServerResponses::Players(server_messages::PlayersResponse {
+                players: message.players,
+            }),
+        );
+    }
+}
+
+impl Handler<server_messages::CorrectionResponse> for Ws {
+    type Result = ();
+
+    fn handle(&mut self, message: server_messages::CorrectionResponse, context: &mut Self::Context) {
+        self.send(context, ServerResponses::Correction(message));
+    }
+}
+
+impl Handler<server_messages::EatenResponse> for Ws {
+    type Result = ();
+
+    fn handle(&mut self, message: server_messages::EatenResponse, context: &mut Self::Context) {
+        self.send(context, ServerResponses::Eaten(message));
+    }
+}
+
+impl Handler<server_messages::ConsumedResponse> for Ws {
+    type Result = ();
+
+    fn handle(&mut self, message: server_messages::ConsumedResponse, context: &mut Self::Context) {
+        self.send(context, ServerResponses::Consumed(message));
+    }
+}
+
+impl Handler<server_messages::CreateResponse> for Ws {
+    type Result = ();
+
+    fn handle(&mut self, message: server_messages::CreateResponse, context: &mut Self::Context) {
+        self.send(context, ServerResponses::Create(message));
+    }
+}
+
+impl Handler<server_messages::PlayerDiedResponse> for Ws {
+    type Result = ();
+
+    fn handle(&mut self, message: server_messages::PlayerDiedResponse, context: &mut Self::Context) {
+        self.send(context, ServerResponses::PlayerDied(message));
+    }
+}
+
+impl Handler<server_messages::LeaderboardResponse> for Ws {
+    type Result = ();
+
+    fn handle(&mut self, message: server_messages::LeaderboardResponse, context: &mut Self::Context) {
+        self.send(context, ServerResponses::Leaderboard(message));
+    }
+}
+
+impl Handler<server_messages::ChatMessageResponse> for Ws {
+    type Result = ();
+
+    fn handle(&mut self, message: server_messages::ChatMessageResponse, context: &mut Self::Context) {
+        self.send(context, ServerResponses::ChatMessage(message));
+    }
+}
+
+impl Handler<server_messages::ErrorResponse> for Ws {
+    type Result = ();
+
+    fn handle(&mut self, message: server_messages::ErrorResponse, context: &mut Self::Context) {
+        self.send(context, ServerResponses::Error(message));
+    }
+}
+
+impl Handler<server_messages::PlayerLeftResponse> for Ws {
+    type Result = ();
+
+    fn handle(&mut self, message: server_messages::PlayerLeftResponse, context: &mut Self::Context) {
+        self.send(context, ServerResponses::PlayerLeft(message));
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::config::{Config, RateLimit};
+    use actix_web::error::PayloadError;
+    use bytes::Bytes;
+    use futures::sync::mpsc;
+    use futures::{stream, Future, Stream};
+
+    const CREATE: &str = r#"{"type": "create", "protocol_version": 1, "viewport_size": {"x": 1000, "y": 800}}"#;
+
+    // Clients have to mask their frames, a null mask leaves the payload as is
+    pub fn client_frame(text: &str) -> Bytes {
+        let mut frame = vec![0x81];
+        if text.len() < 126 {
+            frame.push(0x80 | text.len() as u8);
+        } else {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(text.len() as u16).to_be_bytes());
+        }
+        frame.extend_from_slice(&[0, 0, 0, 0]);
+        frame.extend_from_slice(text.as_bytes());
+        Bytes::from(frame)
+    }
+
+    // The JSON of the text frames sent by the server
+    fn server_messages(mut bytes: &[u8]) -> Vec<serde_json::Value> {
+        let mut messages = Vec::new();
+
+        while bytes.len() >= 2 {
+            let (length, header) = match bytes[1] & 0x7f {
+                126 => (u16::from_be_bytes([bytes[2], bytes[3]]) as usize, 4),
+                127 => {
+                    let mut length = [0; 8];
+                    length.copy_from_slice(&bytes[2..10]);
+                    (u64::from_be_bytes(length) as usize, 10)
+                }
+                length => (length as usize, 2),
+            };
+            if bytes[0] & 0x0f == 1 {
+                messages.push(serde_json::from_slice(&bytes[header..header + length]).unwrap());
+            }
+            bytes = &bytes[header + length..];
+        }
+
+        messages
+    }
+
+    // A connection to the world, fed with client frames and giving back the JSON messages of the server
+    pub fn connect(
+        world_actor: Addr<world::World>,
+        config: &Config,
+    ) -> (
+        mpsc::UnboundedSender<Bytes>,
+        impl Stream<Item = serde_json::Value, Error = ()>,
+    ) {
+        let actor = Ws::new(
+            world_actor,
+            rooms::Rooms::from_config(config.clone()).start(),
+            None,
+            &config.rate_limits,
+            &config.intervals,
+        );
+        let (sender, receiver) = mpsc::unbounded();
+        let payload = receiver.map_err(|()| PayloadError::Incomplete(None));
+        let (_address, frames) = ws::WebsocketContext::create_with_addr(actor, payload);
+        let messages = frames
+            .map_err(|_error| ())
+            .map(|bytes| stream::iter_ok::<_, ()>(server_messages(&bytes)))
+            .flatten();
+
+        (sender, messages)
+    }
+
+    #[test]
+    fn test_ws_second_create() {
+        let mut system = System::new("ws_second_create");
+        let config = Config::default();
+        let (sender, messages) = connect(world::World::from_config(&config).start(), &config);
+
+        // The second one comes while the player is being created
+        sender.unbounded_send(client_frame(CREATE)).unwrap();
+        sender.unbounded_send(client_frame(CREATE)).unwrap();
+
+        let second_create_future = messages
+            .inspect(move |message| {
+                // And this one once it is created
+                if message["type"] == "create" {
+                    sender.unbounded_send(client_frame(CREATE)).unwrap();
+                }
+            })
+            .take(3)
+            .collect()
+            .map(|responses| {
+                assert_eq!(responses[0]["type"], "error");
+                assert_eq!(responses[0]["code"], "already_created");
+                assert_eq!(responses[1]["type"], "create");
+                assert_eq!(responses[2]["type"], "error");
+                assert_eq!(responses[2]["code"], "already_created");
+            });
+
+        system.block_on(second_create_future).expect("System error");
+    }
+
+    #[test]
+    fn test_ws_kick() {
+        let mut system = System::new("ws_kick");
+        let config = Config::default();
+        let world_actor = world::World::from_config(&config).start();
+        let (sender, messages) = connect(world_actor.clone(), &config);
+
+        sender.unbounded_send(client_frame(CREATE)).unwrap();
+
+        let kick_future = messages
+            .into_future()
+            .map_err(|(error, _messages)| error)
+            .and_then(|(created, _messages)| {
+                let created = created.expect("No create response");
+                let id = Uuid::parse_str(created["id"].as_str().unwrap()).unwrap();
+
+                world_actor
+                    .send(world::KickPlayers {
+                        target: world::KickTarget::Player(id),
+                        reason: "Kicked".to_string(),
+                    })
+                    .map_err(|_error| ())
+                    .map(move |kicked| (created, id, kicked))
+            })
+            .and_then(|(created, id, kicked)| {
+                assert_eq!(kicked.unwrap(), vec![id]);
+
+                // The token of the kicked player doesn't give it back
+                let (sender, messages) = connect(world_actor.clone(), &config);
+                let create = format!(
+                    r#"{{"type": "create", "protocol_version": 1, "viewport_size": {{"x": 1000, "y": 800}}, "token": {}}}"#,
+                    created["token"]
+                );
+                sender.unbounded_send(client_frame(&create)).unwrap();
+
+                messages
+                    .into_future()
+                    .map_err(|(error, _messages)| error)
+                    .map(move |(created, _messages)| (id, created, sender))
+            })
+            .map(|(id, created, _sender)| {
+                let created = created.expect("No create response");
+
+                assert_eq!(created["type"], "create");
+                assert_ne!(created["id"], id.to_string());
+            });
+
+        system.block_on(kick_future).expect("System error");
+    }
+
+    #[test]
+    fn test_ws_merged_moves() {
+        let mut system = System::new("ws_merged_moves");
+        let mut config = Config::default();
+        config.rate_limits.moves = RateLimit {
+            per_second: 1.0,
+            burst: 1,
+        };
+        config.rate_limits.abuse = RateLimit {
+            per_second: 1.0,
+            burst: 1,
+        };
+        let (sender, messages) = connect(world::World::from_config(&config).start(), &config);
+
+        sender.unbounded_send(client_frame(CREATE)).unwrap();
+
+        let merged_moves_future = messages
+            .filter(|message| message["type"] == "create" || message["type"] == "error")
+            .into_future()
+            .map_err(|(error, _messages)| error)
+            .and_then(move |(created, messages)| {
+                let created = created.expect("No create response");
+                let move_request = format!(
+                    r#"{{"type": "move", "id": {}, "size": 20, "moved": {{"x": 1, "y": 0}}, "dots_consumed": []}}"#,
+                    created["id"]
+                );
+
+                // The first one goes through, the next one waits for the limit and the others are merged into it
+                for _ in 0..MAX_MERGED_MOVES + 2 {
+                    sender.unbounded_send(client_frame(&move_request)).unwrap();
+                }
+                sender.unbounded_send(client_frame(CREATE)).unwrap();
+                // Past that, the second one goes over the abuse limit
+                sender.unbounded_send(client_frame(&move_request)).unwrap();
+                sender.unbounded_send(client_frame(&move_request)).unwrap();
+
+                messages.collect().map(move |responses| (responses, sender))
+            })
+            .map(|(responses, _sender)| {
+                assert_eq!(responses.len(), 1);
+                assert_eq!(responses[0]["code"], "already_created");
+            });
 
-        context.text(result_json);
+        system.block_on(merged_moves_future).expect("System error");
     }
 }
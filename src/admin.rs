@@ -0,0 +1,208 @@
+use actix::prelude::*;
+use actix_web::{error, http::header, web, Error, HttpRequest, HttpResponse};
+use futures::{future, Future};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::actors::bans::{self, BanTarget, Bans};
+use crate::actors::rooms::{self, Rooms};
+use crate::actors::world::KickTarget;
+
+/// Token the admin requests have to carry. The admin API answers 404 when there is none
+pub struct AdminToken(pub Option<String>);
+
+#[derive(Deserialize)]
+pub struct KickQuery {
+    reason: Option<String>,
+}
+
+#[derive(Serialize)]
+struct KickedResponse {
+    kicked: Vec<Uuid>,
+}
+
+#[derive(Serialize)]
+struct UnbannedResponse {
+    removed: bool,
+}
+
+type AdminResponse = Box<dyn Future<Item = HttpResponse, Error = Error>>;
+
+/// Routes of the admin API, under /admin
+pub fn configure(config: &mut web::ServiceConfig) {
+    config.service(
+        web::scope("/admin")
+            .route("/players", web::get().to_async(list_players))
+            .route("/players/{id}/kick", web::post().to_async(kick_player))
+            .route("/bans", web::get().to_async(list_bans))
+            .route("/bans", web::post().to_async(ban))
+            .route("/bans", web::delete().to_async(unban)),
+    );
+}
+
+fn authorize(request: &HttpRequest, token: &AdminToken) -> Result<(), Error> {
+    let expected = match token.0 {
+        Some(ref expected) => expected,
+        None => return Err(error::ErrorNotFound("The admin API is disabled")),
+    };
+    let given = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match given {
+        Some(given) if constant_time_eq(given.as_bytes(), expected.as_bytes()) => Ok(()),
+        _ => Err(error::ErrorUnauthorized("Invalid admin token")),
+    }
+}
+
+// Doesn't stop at the first difference, so that the time taken doesn't tell how much of the token was right
+fn constant_time_eq(first: &[u8], second: &[u8]) -> bool {
+    first.len() == second.len()
+        && first
+            .iter()
+            .zip(second.iter())
+            .fold(0, |difference, (first, second)| difference | (first ^ second))
+            == 0
+}
+
+fn list_players(
+    request: HttpRequest,
+    token: web::Data<AdminToken>,
+    rooms_actor: web::Data<Addr<Rooms>>,
+) -> AdminResponse {
+    let rooms_actor = rooms_actor.get_ref().clone();
+
+    Box::new(
+        future::result(authorize(&request, &token))
+            .and_then(move |()| {
+                rooms_actor
+                    .send(rooms::ListPlayers)
+                    .and_then(|result| result)
+                    .map_err(error::ErrorInternalServerError)
+            })
+            .map(|worlds| HttpResponse::Ok().json(worlds)),
+    )
+}
+
+fn kick_player(
+    request: HttpRequest,
+    id: web::Path<Uuid>,
+    query: web::Query<KickQuery>,
+    token: web::Data<AdminToken>,
+    rooms_actor: web::Data<Addr<Rooms>>,
+) -> AdminResponse {
+    let rooms_actor = rooms_actor.get_ref().clone();
+    let reason = query
+        .into_inner()
+        .reason
+        .unwrap_or_else(|| "Kicked by an admin".to_string());
+
+    Box::new(
+        future::result(authorize(&request, &token))
+            .and_then(move |()| {
+                rooms_actor
+                    .send(rooms::KickPlayers {
+                        target: KickTarget::Player(id.into_inner()),
+                        reason,
+                    })
+                    .and_then(|result| result)
+                    .map_err(error::ErrorInternalServerError)
+            })
+            .map(|kicked| {
+                if kicked.is_empty() {
+                    HttpResponse::NotFound().body("No such player connected")
+                } else {
+                    HttpResponse::Ok().json(KickedResponse { kicked })
+                }
+            }),
+    )
+}
+
+fn list_bans(request: HttpRequest, token: web::Data<AdminToken>, bans_actor: web::Data<Addr<Bans>>) -> AdminResponse {
+    let bans_actor = bans_actor.get_ref().clone();
+
+    Box::new(
+        future::result(authorize(&request, &token))
+            .and_then(move |()| bans_actor.send(bans::GetBans).map_err(error::ErrorInternalServerError))
+            .map(|bans| HttpResponse::Ok().json(bans)),
+    )
+}
+
+/// Saves the ban, then kicks the players connected from the address
+fn ban(
+    request: HttpRequest,
+    target: web::Json<BanTarget>,
+    token: web::Data<AdminToken>,
+    bans_actor: web::Data<Addr<Bans>>,
+    rooms_actor: web::Data<Addr<Rooms>>,
+) -> AdminResponse {
+    let bans_actor = bans_actor.get_ref().clone();
+    let rooms_actor = rooms_actor.get_ref().clone();
+    let target = target.into_inner();
+
+    Box::new(
+        future::result(authorize(&request, &token))
+            .and_then(move |()| {
+                bans_actor
+                    .send(bans::Ban(target))
+                    .map_err(error::ErrorInternalServerError)
+                    .and_then(|result| result.map_err(error::ErrorInternalServerError))
+            })
+            .and_then(move |()| {
+                rooms_actor
+                    .send(rooms::KickPlayers {
+                        target: target.into(),
+                        reason: "Banned".to_string(),
+                    })
+                    .and_then(|result| result)
+                    .map_err(error::ErrorInternalServerError)
+            })
+            .map(|kicked| HttpResponse::Ok().json(KickedResponse { kicked })),
+    )
+}
+
+fn unban(
+    request: HttpRequest,
+    target: web::Json<BanTarget>,
+    token: web::Data<AdminToken>,
+    bans_actor: web::Data<Addr<Bans>>,
+) -> AdminResponse {
+    let bans_actor = bans_actor.get_ref().clone();
+    let target = target.into_inner();
+
+    Box::new(
+        future::result(authorize(&request, &token))
+            .and_then(move |()| {
+                bans_actor
+                    .send(bans::Unban(target))
+                    .map_err(error::ErrorInternalServerError)
+                    .and_then(|result| result.map_err(error::ErrorInternalServerError))
+            })
+            .map(|removed| HttpResponse::Ok().json(UnbannedResponse { removed })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_admin_authorize() {
+        let token = AdminToken(Some("secret".to_string()));
+        let authorized = TestRequest::default()
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .to_http_request();
+        let wrong = TestRequest::default()
+            .header(header::AUTHORIZATION, "Bearer secreT")
+            .to_http_request();
+        let missing = TestRequest::default().to_http_request();
+
+        assert!(authorize(&authorized, &token).is_ok());
+        assert!(authorize(&wrong, &token).is_err());
+        assert!(authorize(&missing, &token).is_err());
+        assert!(authorize(&authorized, &AdminToken(None)).is_err());
+    }
+}
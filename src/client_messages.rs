@@ -2,27 +2,280 @@ use serde::Deserialize;
 use uuid::Uuid;
 
 use crate::actors::world::Coordinates;
+use crate::consts::{MAX_DOTS_CONSUMED, MAX_NICKNAME_LENGTH, MAX_VIEWPORT_SIZE, MIN_VIEWPORT_SIZE, SKINS_AMOUNT};
 
+/// Every request carries its kind in a "type" field, e.g. {"type": "move", ...}
 #[derive(Deserialize, Debug)]
-#[serde(untagged)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum ClientRequests {
     Create(CreateRequest),
     Move(MoveRequest),
+    Split(SplitRequest),
+    Eject(EjectRequest),
+    Respawn(RespawnRequest),
+    Chat(ChatRequest),
+    #[serde(other)]
     Invalid,
 }
 
+impl ClientRequests {
+    /// The player the request acts on, for the requests that need an existing player
+    pub fn player_id(&self) -> Option<Uuid> {
+        match self {
+            ClientRequests::Move(request) => Some(request.id),
+            ClientRequests::Split(request) => Some(request.id),
+            ClientRequests::Eject(request) => Some(request.id),
+            ClientRequests::Respawn(request) => Some(request.id),
+            ClientRequests::Chat(request) => Some(request.id),
+            ClientRequests::Create(_) | ClientRequests::Invalid => None,
+        }
+    }
+
+    /// Value of the "type" field, used to label the metrics and to pick the rate limit
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ClientRequests::Create(_) => "create",
+            ClientRequests::Move(_) => "move",
+            ClientRequests::Split(_) => "split",
+            ClientRequests::Eject(_) => "eject",
+            ClientRequests::Respawn(_) => "respawn",
+            ClientRequests::Chat(_) => "chat",
+            ClientRequests::Invalid => "invalid",
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct CreateRequest {
+    // Has to match PROTOCOL_VERSION
+    pub protocol_version: u32,
+    // Encoding of the messages the server sends from then on
+    #[serde(default)]
+    pub encoding: Encoding,
     pub viewport_size: Coordinates,
+    // The token of a previous connection, to take back the player after a network drop
+    #[serde(default)]
+    pub token: Option<Uuid>,
+    #[serde(default)]
+    pub nickname: String,
+    #[serde(default)]
+    pub skin: u32,
+}
+
+impl CreateRequest {
+    /// Nicknames are made of letters, digits, spaces, dashes and underscores
+    pub fn validate_profile(&self) -> Result<(), String> {
+        if self.nickname.chars().count() > MAX_NICKNAME_LENGTH {
+            return Err(format!(
+                "Nicknames can't be longer than {} characters",
+                MAX_NICKNAME_LENGTH
+            ));
+        }
+        if !self
+            .nickname
+            .chars()
+            .all(|character| character.is_alphanumeric() || character == ' ' || character == '-' || character == '_')
+        {
+            return Err("Nicknames can only contain letters, digits, spaces, dashes and underscores".to_string());
+        }
+        if self.skin >= SKINS_AMOUNT {
+            return Err(format!("Unknown skin {}", self.skin));
+        }
+
+        Ok(())
+    }
+}
+
+/// Bigger viewports would make the server look through most of the world for every update
+pub fn validate_viewport(viewport_size: Coordinates) -> Result<(), String> {
+    let range = MIN_VIEWPORT_SIZE..=MAX_VIEWPORT_SIZE;
+
+    if !range.contains(&viewport_size.x) || !range.contains(&viewport_size.y) {
+        return Err(format!(
+            "The viewport has to be between {} and {} along each axis",
+            MIN_VIEWPORT_SIZE, MAX_VIEWPORT_SIZE
+        ));
+    }
+
+    Ok(())
 }
 
 #[derive(Deserialize, Debug)]
 pub struct MoveRequest {
     pub id: Uuid,
     pub size: u32,
-    pub moved: Coordinates,
+    pub moved: Delta,
     pub dots_consumed: Vec<Uuid>,
 }
 
+impl MoveRequest {
+    /// A single move doing both, for moves sent faster than the rate limit. The dots consumed are deduplicated
+    /// and capped, so that a flood of moves doesn't grow them without bound
+    pub fn merge(mut self, next: MoveRequest) -> Self {
+        self.size = next.size;
+        self.moved = Delta {
+            x: self.moved.x.saturating_add(next.moved.x),
+            y: self.moved.y.saturating_add(next.moved.y),
+        };
+        self.dots_consumed.extend(next.dots_consumed);
+        self.dots_consumed.sort_unstable();
+        self.dots_consumed.dedup();
+        self.dots_consumed.truncate(MAX_DOTS_CONSUMED);
+        self
+    }
+}
+
+/// Splits every cell big enough in two, shooting the new halves towards split
+#[derive(Deserialize, Debug)]
+pub struct SplitRequest {
+    pub id: Uuid,
+    pub split: Delta,
+}
+
+/// Shoots a bit of mass out of every cell big enough, towards eject
 #[derive(Deserialize, Debug)]
-pub struct Invalid;
+pub struct EjectRequest {
+    pub id: Uuid,
+    pub eject: Delta,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RespawnRequest {
+    pub id: Uuid,
+    pub viewport_size: Coordinates,
+}
+
+/// A chat message sent to every player, or only to the players around the sender in proximity mode
+#[derive(Deserialize, Debug)]
+pub struct ChatRequest {
+    pub id: Uuid,
+    pub message: String,
+    #[serde(default)]
+    pub proximity: bool,
+}
+
+/// Text frames are always JSON, binary frames are always MessagePack with named fields and UUIDs as 16 raw bytes
+#[derive(Deserialize, Debug, Copy, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+/// A signed distance moved along each axis since the previous move request.
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct Delta {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_requests_tag() {
+        let create = r#"{"type": "create", "protocol_version": 1, "viewport_size": {"x": 1000, "y": 800}}"#;
+        let split = r#"{"type": "split", "id": "f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4", "split": {"x": -1, "y": 0}}"#;
+        let chat = r#"{"type": "chat", "id": "f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4", "message": "hello"}"#;
+        let unknown = r#"{"type": "teleport", "id": "f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4"}"#;
+        let untagged = r#"{"protocol_version": 1, "viewport_size": {"x": 1000, "y": 800}}"#;
+
+        match serde_json::from_str(create) {
+            Ok(ClientRequests::Create(request)) => {
+                assert_eq!(request.protocol_version, 1);
+                assert_eq!(request.viewport_size, Coordinates { x: 1000, y: 800 });
+                assert!(request.token.is_none());
+                assert_eq!(request.encoding, Encoding::Json);
+            }
+            _ => panic!("Create request wasn't parsed"),
+        }
+        match serde_json::from_str(split) {
+            Ok(ClientRequests::Split(request)) => assert_eq!(request.split, Delta { x: -1, y: 0 }),
+            _ => panic!("Split request wasn't parsed"),
+        }
+        match serde_json::from_str(chat) {
+            Ok(ClientRequests::Chat(request)) => {
+                assert_eq!(request.message, "hello");
+                assert!(!request.proximity);
+            }
+            _ => panic!("Chat request wasn't parsed"),
+        }
+        match serde_json::from_str(unknown) {
+            Ok(ClientRequests::Invalid) => {}
+            _ => panic!("Unknown request wasn't invalid"),
+        }
+        assert!(serde_json::from_str::<ClientRequests>(untagged).is_err());
+    }
+
+    #[test]
+    fn test_create_request_profile() {
+        let parse = |nickname: &str, skin: u32| -> CreateRequest {
+            let request = serde_json::json!({
+                "protocol_version": 1,
+                "viewport_size": {"x": 1000, "y": 800},
+                "nickname": nickname,
+                "skin": skin,
+            });
+            serde_json::from_value(request).unwrap()
+        };
+
+        assert!(parse("", 0).validate_profile().is_ok());
+        assert!(parse("Jöhn_Doe-42 x", SKINS_AMOUNT - 1).validate_profile().is_ok());
+        assert!(parse("<script>", 0).validate_profile().is_err());
+        assert!(parse("a very long nickname", 0).validate_profile().is_err());
+        assert!(parse("john", SKINS_AMOUNT).validate_profile().is_err());
+    }
+
+    #[test]
+    fn test_validate_viewport() {
+        assert!(validate_viewport(Coordinates { x: 1000, y: 800 }).is_ok());
+        assert!(validate_viewport(Coordinates {
+            x: MIN_VIEWPORT_SIZE,
+            y: MAX_VIEWPORT_SIZE
+        })
+        .is_ok());
+        assert!(validate_viewport(Coordinates { x: 100, y: 800 }).is_err());
+        assert!(validate_viewport(Coordinates { x: 1000, y: u32::MAX }).is_err());
+    }
+
+    #[test]
+    fn test_client_requests_message_pack() {
+        let create = serde_json::json!({
+            "type": "create",
+            "protocol_version": 1,
+            "encoding": "message_pack",
+            "viewport_size": {"x": 1000, "y": 800},
+        });
+        let payload = rmp_serde::to_vec_named(&create).unwrap();
+
+        match rmp_serde::from_slice(&payload) {
+            Ok(ClientRequests::Create(request)) => {
+                assert_eq!(request.encoding, Encoding::MessagePack);
+                assert_eq!(request.viewport_size, Coordinates { x: 1000, y: 800 });
+            }
+            _ => panic!("Create request wasn't parsed"),
+        }
+    }
+
+    #[test]
+    fn test_move_request_merge() {
+        let dot = Uuid::new_v4();
+        let request = |x: i32, dots_consumed: Vec<Uuid>| MoveRequest {
+            id: Uuid::nil(),
+            size: 20,
+            moved: Delta { x, y: 0 },
+            dots_consumed,
+        };
+
+        let merged = request(10, vec![dot]).merge(request(-4, vec![dot, Uuid::new_v4()]));
+        assert_eq!(merged.moved, Delta { x: 6, y: 0 });
+        assert_eq!(merged.dots_consumed.len(), 2);
+        assert!(merged.dots_consumed.contains(&dot));
+
+        let flood = (0..MAX_DOTS_CONSUMED).map(|_| Uuid::new_v4()).collect();
+        let merged = merged.merge(request(0, flood));
+        assert_eq!(merged.dots_consumed.len(), MAX_DOTS_CONSUMED);
+    }
+}
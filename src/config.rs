@@ -0,0 +1,433 @@
+use serde::{Deserialize, Deserializer};
+use structopt::StructOpt;
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::actors::world::Coordinates;
+use crate::consts::{
+    ABUSE_RATE_LIMIT, CHAT_RATE_LIMIT, CLIENT_TIMEOUT, CREATE_RATE_LIMIT, DISCONNECT_GRACE_PERIOD,
+    DOTS_CREATE_INTERVAL, DOTS_MOVE_INTERVAL, DOTS_SEND_INTERVAL, EJECT_RATE_LIMIT, LEADERBOARD_SEND_INTERVAL,
+    MAX_DOTS_AMOUNT, MAX_PLAYERS_PER_WORLD, MAX_VIRUSES_AMOUNT, MAX_WORLDS, MOVE_RATE_LIMIT, PING_SEND_INTERVAL,
+    PLAYERS_SEND_INTERVAL, RESPAWN_RATE_LIMIT, ROOM_TEARDOWN_DELAY, SPLIT_RATE_LIMIT, VIRUSES_CREATE_INTERVAL,
+    WORLD_X_SIZE, WORLD_Y_SIZE,
+};
+
+/// Settings that can change between two runs of the server. The ones missing from the file keep the values of consts.rs
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub address: String,
+    pub world_size: Coordinates,
+    pub max_dots_amount: u32,
+    pub max_viruses_amount: u32,
+    pub max_players_per_world: usize,
+    pub max_worlds: usize,
+    pub intervals: Intervals,
+    pub rate_limits: RateLimits,
+    // The admin API is disabled without a token
+    pub admin_token: Option<String>,
+    pub bans_file: PathBuf,
+}
+
+/// Written in milliseconds in the file
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Intervals {
+    #[serde(deserialize_with = "milliseconds")]
+    pub dots_send: Duration,
+    #[serde(deserialize_with = "milliseconds")]
+    pub players_send: Duration,
+    #[serde(deserialize_with = "milliseconds")]
+    pub leaderboard_send: Duration,
+    #[serde(deserialize_with = "milliseconds")]
+    pub dots_create: Duration,
+    #[serde(deserialize_with = "milliseconds")]
+    pub dots_move: Duration,
+    #[serde(deserialize_with = "milliseconds")]
+    pub viruses_create: Duration,
+    #[serde(deserialize_with = "milliseconds")]
+    pub disconnect_grace_period: Duration,
+    #[serde(deserialize_with = "milliseconds")]
+    pub room_teardown_delay: Duration,
+    #[serde(deserialize_with = "milliseconds")]
+    pub ping_send: Duration,
+    // Longer than ping_send, so that clients get a chance to answer
+    #[serde(deserialize_with = "milliseconds")]
+    pub client_timeout: Duration,
+}
+
+/// Limits of the messages each connection can send, by message type
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    pub create: RateLimit,
+    // Moves over the limit are merged into the next move sent instead of being refused
+    #[serde(rename = "move")]
+    pub moves: RateLimit,
+    pub split: RateLimit,
+    pub eject: RateLimit,
+    pub respawn: RateLimit,
+    pub chat: RateLimit,
+    // Applies to the messages refused and the invalid ones. The client is disconnected past it
+    pub abuse: RateLimit,
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+/// Every setting of the file can be overridden from the command line
+#[derive(StructOpt, Debug)]
+#[structopt(name = "agar-server")]
+pub struct Options {
+    /// TOML configuration file
+    #[structopt(short, long, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+    /// Address to listen on, e.g. 0.0.0.0:5555
+    #[structopt(long)]
+    pub address: Option<String>,
+    #[structopt(long)]
+    pub world_width: Option<u32>,
+    #[structopt(long)]
+    pub world_height: Option<u32>,
+    #[structopt(long)]
+    pub max_dots_amount: Option<u32>,
+    #[structopt(long)]
+    pub max_viruses_amount: Option<u32>,
+    #[structopt(long)]
+    pub max_players_per_world: Option<usize>,
+    /// Worlds across every room
+    #[structopt(long)]
+    pub max_worlds: Option<usize>,
+    /// In milliseconds, like the other intervals
+    #[structopt(long)]
+    pub dots_send_interval: Option<u64>,
+    #[structopt(long)]
+    pub players_send_interval: Option<u64>,
+    #[structopt(long)]
+    pub leaderboard_send_interval: Option<u64>,
+    #[structopt(long)]
+    pub dots_create_interval: Option<u64>,
+    #[structopt(long)]
+    pub dots_move_interval: Option<u64>,
+    #[structopt(long)]
+    pub viruses_create_interval: Option<u64>,
+    #[structopt(long)]
+    pub disconnect_grace_period: Option<u64>,
+    #[structopt(long)]
+    pub room_teardown_delay: Option<u64>,
+    #[structopt(long)]
+    pub ping_send_interval: Option<u64>,
+    #[structopt(long)]
+    pub client_timeout: Option<u64>,
+    /// Written <type>=<per second>/<burst>, e.g. move=120/60. Can be repeated
+    #[structopt(long = "rate-limit", parse(try_from_str = parse_rate_limit))]
+    pub rate_limits: Vec<(String, RateLimit)>,
+    /// Token the admin API requests have to send as "Authorization: Bearer <token>"
+    #[structopt(long, env = "AGAR_ADMIN_TOKEN", hide_env_values = true)]
+    pub admin_token: Option<String>,
+    /// JSON file the bans are saved to
+    #[structopt(long, parse(from_os_str))]
+    pub bans_file: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            address: "127.0.0.1:5555".to_string(),
+            world_size: Coordinates {
+                x: WORLD_X_SIZE,
+                y: WORLD_Y_SIZE,
+            },
+            max_dots_amount: MAX_DOTS_AMOUNT,
+            max_viruses_amount: MAX_VIRUSES_AMOUNT,
+            max_players_per_world: MAX_PLAYERS_PER_WORLD,
+            max_worlds: MAX_WORLDS,
+            intervals: Intervals::default(),
+            rate_limits: RateLimits::default(),
+            admin_token: None,
+            bans_file: PathBuf::from("bans.json"),
+        }
+    }
+}
+
+impl Default for Intervals {
+    fn default() -> Self {
+        Intervals {
+            dots_send: DOTS_SEND_INTERVAL,
+            players_send: PLAYERS_SEND_INTERVAL,
+            leaderboard_send: LEADERBOARD_SEND_INTERVAL,
+            dots_create: DOTS_CREATE_INTERVAL,
+            dots_move: DOTS_MOVE_INTERVAL,
+            viruses_create: VIRUSES_CREATE_INTERVAL,
+            disconnect_grace_period: DISCONNECT_GRACE_PERIOD,
+            room_teardown_delay: ROOM_TEARDOWN_DELAY,
+            ping_send: PING_SEND_INTERVAL,
+            client_timeout: CLIENT_TIMEOUT,
+        }
+    }
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            create: CREATE_RATE_LIMIT.into(),
+            moves: MOVE_RATE_LIMIT.into(),
+            split: SPLIT_RATE_LIMIT.into(),
+            eject: EJECT_RATE_LIMIT.into(),
+            respawn: RESPAWN_RATE_LIMIT.into(),
+            chat: CHAT_RATE_LIMIT.into(),
+            abuse: ABUSE_RATE_LIMIT.into(),
+        }
+    }
+}
+
+impl From<(f64, u32)> for RateLimit {
+    fn from((per_second, burst): (f64, u32)) -> Self {
+        RateLimit { per_second, burst }
+    }
+}
+
+impl RateLimits {
+    /// The limits of the message types, by the value of their "type" field
+    pub fn by_type(&self) -> [(&'static str, RateLimit); 6] {
+        [
+            ("create", self.create),
+            ("move", self.moves),
+            ("split", self.split),
+            ("eject", self.eject),
+            ("respawn", self.respawn),
+            ("chat", self.chat),
+        ]
+    }
+
+    fn get_mut(&mut self, kind: &str) -> Option<&mut RateLimit> {
+        match kind {
+            "create" => Some(&mut self.create),
+            "move" => Some(&mut self.moves),
+            "split" => Some(&mut self.split),
+            "eject" => Some(&mut self.eject),
+            "respawn" => Some(&mut self.respawn),
+            "chat" => Some(&mut self.chat),
+            "abuse" => Some(&mut self.abuse),
+            _ => None,
+        }
+    }
+}
+
+fn parse_rate_limit(value: &str) -> Result<(String, RateLimit), String> {
+    let invalid = || format!("Invalid rate limit {}, expected <type>=<per second>/<burst>", value);
+    let (kind, limit) = value.split_once('=').ok_or_else(invalid)?;
+    let (per_second, burst) = limit.split_once('/').ok_or_else(invalid)?;
+
+    if RateLimits::default().get_mut(kind).is_none() {
+        return Err(format!("Unknown message type {}", kind));
+    }
+
+    Ok((
+        kind.to_string(),
+        RateLimit {
+            per_second: per_second.parse().map_err(|_error| invalid())?,
+            burst: burst.parse().map_err(|_error| invalid())?,
+        },
+    ))
+}
+
+fn milliseconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_millis)
+}
+
+impl Config {
+    /// Reads the file given on the command line if any, then applies the command line overrides
+    pub fn load(options: Options) -> Result<Self, String> {
+        let mut config = match options.config {
+            Some(ref path) => {
+                let content =
+                    fs::read_to_string(path).map_err(|error| format!("Couldn't read {}: {}", path.display(), error))?;
+                toml::from_str(&content).map_err(|error| format!("Couldn't parse {}: {}", path.display(), error))?
+            }
+            None => Config::default(),
+        };
+
+        config.apply(options);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn apply(&mut self, options: Options) {
+        macro_rules! set {
+            ($target:expr, $value:expr) => {
+                if let Some(value) = $value {
+                    $target = value;
+                }
+            };
+        }
+
+        set!(self.address, options.address);
+        set!(self.world_size.x, options.world_width);
+        set!(self.world_size.y, options.world_height);
+        set!(self.max_dots_amount, options.max_dots_amount);
+        set!(self.max_viruses_amount, options.max_viruses_amount);
+        set!(self.max_players_per_world, options.max_players_per_world);
+        set!(self.max_worlds, options.max_worlds);
+        set!(
+            self.intervals.dots_send,
+            options.dots_send_interval.map(Duration::from_millis)
+        );
+        set!(
+            self.intervals.players_send,
+            options.players_send_interval.map(Duration::from_millis)
+        );
+        set!(
+            self.intervals.leaderboard_send,
+            options.leaderboard_send_interval.map(Duration::from_millis)
+        );
+        set!(
+            self.intervals.dots_create,
+            options.dots_create_interval.map(Duration::from_millis)
+        );
+        set!(
+            self.intervals.dots_move,
+            options.dots_move_interval.map(Duration::from_millis)
+        );
+        set!(
+            self.intervals.viruses_create,
+            options.viruses_create_interval.map(Duration::from_millis)
+        );
+        set!(
+            self.intervals.disconnect_grace_period,
+            options.disconnect_grace_period.map(Duration::from_millis)
+        );
+        set!(
+            self.intervals.room_teardown_delay,
+            options.room_teardown_delay.map(Duration::from_millis)
+        );
+        set!(
+            self.intervals.ping_send,
+            options.ping_send_interval.map(Duration::from_millis)
+        );
+        set!(
+            self.intervals.client_timeout,
+            options.client_timeout.map(Duration::from_millis)
+        );
+        for (kind, limit) in options.rate_limits {
+            set!(
+                *self.rate_limits.get_mut(&kind).expect("Checked when parsed"),
+                Some(limit)
+            );
+        }
+        set!(self.admin_token, options.admin_token.map(Some));
+        set!(self.bans_file, options.bans_file);
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        // Cells, dots and viruses have to fit in the world
+        if self.world_size.x < 1000 || self.world_size.y < 1000 {
+            return Err("The world has to be at least 1000 by 1000".to_string());
+        }
+        if self.max_players_per_world == 0 {
+            return Err("Worlds have to accept at least one player".to_string());
+        }
+        if self.max_worlds == 0 {
+            return Err("The server has to accept at least one world".to_string());
+        }
+        let mut rate_limits = self.rate_limits.by_type().to_vec();
+        rate_limits.push(("abuse", self.rate_limits.abuse));
+        for (kind, limit) in rate_limits {
+            if limit.per_second.is_nan() || limit.per_second <= 0.0 || limit.burst == 0 {
+                return Err(format!("The {} rate limit has to let messages through", kind));
+            }
+        }
+        if self.admin_token.as_ref().is_some_and(|token| token.is_empty()) {
+            return Err("The admin token can't be empty".to_string());
+        }
+
+        let intervals = [
+            self.intervals.dots_send,
+            self.intervals.players_send,
+            self.intervals.leaderboard_send,
+            self.intervals.dots_create,
+            self.intervals.dots_move,
+            self.intervals.viruses_create,
+            self.intervals.ping_send,
+        ];
+        if intervals.iter().any(|interval| *interval == Duration::from_millis(0)) {
+            return Err("Intervals can't be 0".to_string());
+        }
+        if self.intervals.client_timeout <= self.intervals.ping_send {
+            return Err("The client timeout has to be longer than the ping send interval".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_file_and_overrides() {
+        let mut config: Config = toml::from_str(
+            r#"
+            address = "0.0.0.0:8080"
+            world_size = { x = 5000, y = 4000 }
+            max_dots_amount = 500
+
+            [intervals]
+            dots_move = 100
+            ping_send = 1000
+
+            [rate_limits.move]
+            per_second = 30
+            burst = 10
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.world_size, Coordinates { x: 5000, y: 4000 });
+        assert_eq!(config.max_dots_amount, 500);
+        assert_eq!(config.max_viruses_amount, MAX_VIRUSES_AMOUNT);
+        assert_eq!(config.intervals.dots_move, Duration::from_millis(100));
+        assert_eq!(config.intervals.dots_send, DOTS_SEND_INTERVAL);
+        assert_eq!(config.intervals.ping_send, Duration::from_secs(1));
+        assert_eq!(config.intervals.client_timeout, CLIENT_TIMEOUT);
+        assert_eq!(config.rate_limits.moves, (30.0, 10).into());
+        assert_eq!(config.rate_limits.chat, CHAT_RATE_LIMIT.into());
+
+        config.apply(Options::from_iter(vec![
+            "agar-server",
+            "--world-width",
+            "3000",
+            "--dots-send-interval",
+            "250",
+            "--rate-limit",
+            "chat=0.5/2",
+            "--client-timeout",
+            "5000",
+        ]));
+
+        assert_eq!(config.address, "0.0.0.0:8080");
+        assert_eq!(config.world_size, Coordinates { x: 3000, y: 4000 });
+        assert_eq!(config.intervals.dots_send, Duration::from_millis(250));
+        assert_eq!(config.intervals.client_timeout, Duration::from_secs(5));
+        assert_eq!(config.rate_limits.chat, (0.5, 2).into());
+        assert_eq!(config.rate_limits.moves, (30.0, 10).into());
+        assert!(config.validate().is_ok());
+
+        config.intervals.client_timeout = config.intervals.ping_send;
+        assert!(config.validate().is_err());
+        config.intervals.client_timeout = CLIENT_TIMEOUT;
+        config.intervals.dots_create = Duration::from_millis(0);
+        assert!(config.validate().is_err());
+        assert!(toml::from_str::<Config>("max_dot_amount = 10").is_err());
+        assert!(parse_rate_limit("teleport=1/1").is_err());
+        assert!(parse_rate_limit("move=fast").is_err());
+    }
+}
@@ -1,20 +1,114 @@
 use std::time::Duration;
 
+// Version of the client and server messages. Clients speaking another version are disconnected
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// Rooms info
+// Room joined by the clients connecting without a room name
+pub const DEFAULT_ROOM: &str = "default";
+pub const MAX_ROOM_NAME_LENGTH: usize = 32;
+// Connections a world of a room accepts before another world is created for the room
+pub const MAX_PLAYERS_PER_WORLD: usize = 50;
+// Worlds across every room. Players joining a room that would need another world are refused past it
+pub const MAX_WORLDS: usize = 64;
+
 // World info
 pub const WORLD_X_SIZE: u32 = 20_000;
 pub const WORLD_Y_SIZE: u32 = 20_000;
 pub const DELTA_VIEWPORT: u32 = 100;
+// Viewport sizes clients can ask for, along each axis
+pub const MIN_VIEWPORT_SIZE: u32 = 200;
+pub const MAX_VIEWPORT_SIZE: u32 = 4000;
 
 // Player info
 pub const DEFAULT_PLAYER_SIZE: u32 = 20;
+// Speed in world units per second of a player with DEFAULT_PLAYER_SIZE
+pub const PLAYER_BASE_SPEED: u32 = 500;
+pub const PLAYER_MIN_SPEED: u32 = 100;
+// Extra distance allowed per move to absorb rounding and network jitter
+pub const MOVE_TOLERANCE: u32 = 10;
+
+// In characters. Players without a nickname are anonymous
+pub const MAX_NICKNAME_LENGTH: usize = 16;
+// Skins clients can pick from, identified from 0 to SKINS_AMOUNT - 1
+pub const SKINS_AMOUNT: u32 = 32;
+
+// Size of the regions players are bucketed by
+pub const PLAYERS_GRID_BUCKET_SIZE: u32 = 1000;
+
+// Leaderboard info
+// Amount of players at the top of the leaderboard sent to every client
+pub const LEADERBOARD_SIZE: usize = 10;
+
+// Chat info
+// In characters
+pub const MAX_CHAT_MESSAGE_LENGTH: usize = 200;
+// Distance from the sender within which players receive a proximity chat message
+pub const CHAT_PROXIMITY_RADIUS: u32 = 2000;
+
+// Cells info
+pub const MAX_CELLS_AMOUNT: usize = 16;
+// Cells smaller than this can't split, so that both halves are at least DEFAULT_PLAYER_SIZE
+pub const MIN_SPLIT_SIZE: u32 = 2 * DEFAULT_PLAYER_SIZE;
+// Initial speed of a cell that just split off, in world units per second
+pub const SPLIT_SPEED: f64 = 1500.0;
+// In world units per second squared
+pub const SPLIT_DECELERATION: f64 = 3000.0;
 
 // Dots info
 pub const MAX_DOTS_AMOUNT: u32 = 10_000;
 pub const DOT_SIZE: u32 = 10;
+pub const DOT_MASS: u32 = 1;
+// Dots a merged move keeps, well over what the cells of a player can reach between two moves
+pub const MAX_DOTS_CONSUMED: usize = 1024;
+// Size of the regions dots are bucketed by
+pub const DOTS_GRID_BUCKET_SIZE: u32 = 500;
+
+// Eject info
+// Mass a cell loses when ejecting, which is also the mass of the ejected dot
+pub const EJECT_MASS: u32 = 4;
+// Cells can't eject below DEFAULT_PLAYER_SIZE
+pub const MIN_EJECT_SIZE: u32 = DEFAULT_PLAYER_SIZE + EJECT_MASS;
+// Initial speed of an ejected dot, in world units per second
+pub const EJECT_SPEED: f64 = 1000.0;
+// In world units per second squared
+pub const EJECT_DECELERATION: f64 = 2000.0;
+
+// Viruses info
+pub const MAX_VIRUSES_AMOUNT: u32 = 50;
+pub const VIRUS_SIZE: u32 = 100;
+// Mass a virus has to be fed with ejected dots before it shoots a new virus
+pub const VIRUS_FEED_LIMIT: u32 = 7 * EJECT_MASS;
+pub const VIRUS_SHOOT_DISTANCE: u32 = 400;
 
 // Time
 pub const PING_SEND_INTERVAL: Duration = Duration::from_secs(2);
-pub const DOTS_SEND_INTERVAL: Duration = Duration::from_secs(4);
+pub const DOTS_SEND_INTERVAL: Duration = Duration::from_secs(1);
 pub const PLAYERS_SEND_INTERVAL: Duration = Duration::from_secs(1);
+pub const LEADERBOARD_SEND_INTERVAL: Duration = Duration::from_secs(2);
+// Clients that didn't answer a ping for that long are disconnected
 pub const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+// Messages of each type a client can send per second, and how many it can send at once after being idle
+pub const CREATE_RATE_LIMIT: (f64, u32) = (1.0, 3);
+pub const MOVE_RATE_LIMIT: (f64, u32) = (120.0, 60);
+pub const SPLIT_RATE_LIMIT: (f64, u32) = (5.0, 5);
+pub const EJECT_RATE_LIMIT: (f64, u32) = (10.0, 10);
+pub const RESPAWN_RATE_LIMIT: (f64, u32) = (1.0, 2);
+pub const CHAT_RATE_LIMIT: (f64, u32) = (1.0, 3);
+// Messages refused or invalid per second, and at once, before the client is disconnected
+pub const ABUSE_RATE_LIMIT: (f64, u32) = (50.0, 200);
+// Moves merged while waiting for the move rate limit. Only the ones past it count as abuse
+pub const MAX_MERGED_MOVES: u32 = 10;
+// How long the cell of a disconnected player stays in the world, waiting for the client to reconnect
+pub const DISCONNECT_GRACE_PERIOD: Duration = Duration::from_secs(15);
+// How long an empty world is kept, so that its disconnected players can still come back
+pub const ROOM_TEARDOWN_DELAY: Duration = DISCONNECT_GRACE_PERIOD;
+// How long an actor has to answer a health check, for each actor the check goes through
+pub const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(1);
 pub const DOTS_CREATE_INTERVAL: Duration = Duration::from_secs(5);
+pub const DOTS_MOVE_INTERVAL: Duration = Duration::from_millis(50);
+pub const VIRUSES_CREATE_INTERVAL: Duration = Duration::from_secs(10);
+// Time before cells of the same player that split can merge back
+pub const MERGE_COOLDOWN: Duration = Duration::from_secs(15);
+// Longest time between two moves that is taken into account when validating the distance moved
+pub const MAX_MOVE_INTERVAL: Duration = Duration::from_millis(500);
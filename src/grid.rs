@@ -0,0 +1,149 @@
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use crate::actors::world::Coordinates;
+
+/// Uniform grid bucketing entities by the region of the world they cover, so that
+/// looking for the entities in an area only touches the buckets overlapping it.
+#[derive(Clone, Debug)]
+pub struct Grid<K: Eq + Hash> {
+    bucket_size: u32,
+    // Last bucket along each axis, queries never go past it
+    last_bucket: (u32, u32),
+    buckets: HashMap<(u32, u32), HashSet<K>>,
+    // Where each key was inserted, to find its buckets back
+    entries: HashMap<K, (Coordinates, u32)>,
+}
+
+impl<K: Eq + Hash + Copy> Grid<K> {
+    /// The grid covers the area from (0, 0) to size
+    pub fn new(bucket_size: u32, size: Coordinates) -> Self {
+        Grid {
+            bucket_size,
+            last_bucket: (size.x / bucket_size, size.y / bucket_size),
+            buckets: HashMap::new(),
+            entries: HashMap::new(),
+        }
+    }
+
+    // Buckets covered by the square of the given size whose top left corner is at coordinates
+    fn buckets_range(&self, coordinates: Coordinates, size: u32) -> (u32, u32, u32, u32) {
+        (
+            coordinates.x / self.bucket_size,
+            coordinates.y / self.bucket_size,
+            coordinates.x.saturating_add(size) / self.bucket_size,
+            coordinates.y.saturating_add(size) / self.bucket_size,
+        )
+    }
+
+    /// Inserts the key, or moves it if it was already in the grid
+    pub fn insert(&mut self, key: K, coordinates: Coordinates, size: u32) {
+        if self.entries.get(&key) == Some(&(coordinates, size)) {
+            return;
+        }
+
+        self.remove(key);
+        self.entries.insert(key, (coordinates, size));
+
+        let (min_x, min_y, max_x, max_y) = self.buckets_range(coordinates, size);
+
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                self.buckets.entry((x, y)).or_default().insert(key);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: K) {
+        let (coordinates, size) = match self.entries.remove(&key) {
+            Some(entry) => entry,
+            None => return,
+        };
+        let (min_x, min_y, max_x, max_y) = self.buckets_range(coordinates, size);
+
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                if let Some(bucket) = self.buckets.get_mut(&(x, y)) {
+                    bucket.remove(&key);
+                    if bucket.is_empty() {
+                        self.buckets.remove(&(x, y));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Keys in the buckets overlapping the area. They still have to be checked against the exact area
+    pub fn query(&self, min: Coordinates, max: Coordinates) -> HashSet<K> {
+        let (min_x, min_y) = (min.x / self.bucket_size, min.y / self.bucket_size);
+        let max_x = cmp::min(max.x / self.bucket_size, self.last_bucket.0);
+        let max_y = cmp::min(max.y / self.bucket_size, self.last_bucket.1);
+        let mut keys = HashSet::new();
+
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                if let Some(bucket) = self.buckets.get(&(x, y)) {
+                    keys.extend(bucket.iter().copied());
+                }
+            }
+        }
+
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_query() {
+        let mut grid = Grid::new(100, Coordinates { x: 2000, y: 2000 });
+
+        grid.insert(1, Coordinates { x: 10, y: 10 }, 10);
+        grid.insert(2, Coordinates { x: 150, y: 10 }, 10);
+        grid.insert(3, Coordinates { x: 90, y: 90 }, 20);
+        grid.insert(4, Coordinates { x: 1000, y: 1000 }, 10);
+
+        let keys = grid.query(Coordinates { x: 0, y: 0 }, Coordinates { x: 50, y: 50 });
+        assert_eq!(keys, vec![1, 3].into_iter().collect());
+
+        let keys = grid.query(Coordinates { x: 120, y: 120 }, Coordinates { x: 190, y: 190 });
+        assert_eq!(keys, vec![3].into_iter().collect());
+
+        grid.remove(3);
+        grid.insert(2, Coordinates { x: 150, y: 150 }, 10);
+
+        let keys = grid.query(Coordinates { x: 0, y: 0 }, Coordinates { x: 199, y: 99 });
+        assert_eq!(keys, vec![1].into_iter().collect());
+        assert_eq!(grid.buckets.len(), 3);
+        assert_eq!(grid.entries.len(), 3);
+    }
+
+    #[test]
+    fn test_grid_query_outside() {
+        let mut grid = Grid::new(100, Coordinates { x: 2000, y: 2000 });
+
+        grid.insert(1, Coordinates { x: 1990, y: 1990 }, 10);
+
+        // Only the buckets of the grid are walked, however big the area
+        let keys = grid.query(
+            Coordinates { x: 0, y: 0 },
+            Coordinates {
+                x: u32::MAX,
+                y: u32::MAX,
+            },
+        );
+        assert_eq!(keys, vec![1].into_iter().collect());
+
+        let keys = grid.query(
+            Coordinates { x: 5000, y: 5000 },
+            Coordinates {
+                x: u32::MAX,
+                y: u32::MAX,
+            },
+        );
+        assert!(keys.is_empty());
+    }
+}
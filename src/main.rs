@@ -6,39 +6,153 @@ extern crate serde;
 extern crate serde_json;
 
 use actix::prelude::*;
-use actix_web::{web, App, Error, HttpRequest, HttpResponse, HttpServer};
+use actix_web::{error, web, App, Error, HttpRequest, HttpResponse, HttpServer};
+use env_logger::Env;
+use futures::{future, Future};
+use log::info;
+use structopt::StructOpt;
+
+use std::io;
 
 mod actors;
+mod admin;
 mod client_messages;
+mod config;
 mod consts;
+mod grid;
+mod metrics;
+mod rate_limit;
 mod server_messages;
 mod utils;
 
-use actors::{world, ws};
+use actors::{bans, rooms, ws};
+use config::{Config, Intervals, Options, RateLimits};
+use consts::{DEFAULT_ROOM, HEALTH_CHECK_TIMEOUT};
 
 fn index(
     request: HttpRequest,
     stream: web::Payload,
-    world_actor: web::Data<Addr<world::World>>,
-) -> Result<HttpResponse, Error> {
-    actix_web_actors::ws::start(ws::Ws::new(world_actor.get_ref().clone()), &request, stream)
+    rooms_actor: web::Data<Addr<rooms::Rooms>>,
+    bans_actor: web::Data<Addr<bans::Bans>>,
+    rate_limits: web::Data<RateLimits>,
+    intervals: web::Data<Intervals>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let room = request.match_info().get("room").unwrap_or(DEFAULT_ROOM).to_string();
+    if !rooms::is_valid_name(&room) {
+        return Box::new(future::ok(HttpResponse::BadRequest().body("Invalid room name")));
+    }
+
+    let rooms_actor = rooms_actor.get_ref().clone();
+    let bans_actor = bans_actor.get_ref().clone();
+    let ip = request.peer_addr().map(|address| address.ip());
+
+    Box::new(
+        bans_actor
+            .send(bans::IsBanned(ip))
+            .map_err(error::ErrorInternalServerError)
+            .and_then(move |result| {
+                if result.0 {
+                    info!("Refused banned address {:?}", ip);
+                    return future::Either::A(future::ok(HttpResponse::Forbidden().body("Banned")));
+                }
+
+                future::Either::B(
+                    rooms_actor
+                        .send(rooms::JoinRoom(room))
+                        .map_err(error::ErrorInternalServerError)
+                        .and_then(move |result| {
+                            let world_actor = match result {
+                                Ok(joined) => joined.0,
+                                Err(error) => return Ok(HttpResponse::ServiceUnavailable().body(error)),
+                            };
+                            let actor = ws::Ws::new(
+                                world_actor.clone(),
+                                rooms_actor.clone(),
+                                ip,
+                                &rate_limits,
+                                &intervals,
+                            );
+
+                            actix_web_actors::ws::start(actor, &request, stream).inspect_err(|_error| {
+                                // The connection never started, so it won't leave the room by itself
+                                rooms_actor.do_send(rooms::LeaveRoom(world_actor));
+                            })
+                        }),
+                )
+            }),
+    )
+}
+
+/// Liveness: answers once the rooms actor did. A hung world doesn't get the whole server restarted
+fn alive(rooms_actor: web::Data<Addr<rooms::Rooms>>) -> impl Future<Item = HttpResponse, Error = Error> {
+    rooms_actor
+        .send(rooms::Ping)
+        .timeout(HEALTH_CHECK_TIMEOUT)
+        .then(|result| match result {
+            Ok(()) => Ok(HttpResponse::Ok().body("ok")),
+            Err(error) => Ok(HttpResponse::ServiceUnavailable().body(format!("Rooms didn't answer: {}", error))),
+        })
+}
+
+/// Readiness: answers once the rooms actor and every world with its actors answered, so that no new players are
+/// sent to the server while one of them is hung
+fn ready(rooms_actor: web::Data<Addr<rooms::Rooms>>) -> impl Future<Item = HttpResponse, Error = Error> {
+    rooms_actor
+        .send(rooms::CheckHealth)
+        .timeout(HEALTH_CHECK_TIMEOUT * 3)
+        .then(|result| match result {
+            Ok(Ok(worlds)) => Ok(HttpResponse::Ok().body(format!("ok, {} worlds", worlds))),
+            Ok(Err(error)) => Ok(HttpResponse::ServiceUnavailable().body(error)),
+            Err(error) => Ok(HttpResponse::ServiceUnavailable().body(format!("Rooms didn't answer: {}", error))),
+        })
+}
+
+fn metrics() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::render())
 }
 
 pub struct Data {
     pub age: i32,
 }
 
-fn main() -> std::io::Result<()> {
-    println!("Running on 127.0.0.1:5555");
+fn main() -> io::Result<()> {
+    env_logger::from_env(Env::default().default_filter_or("info")).init();
+    metrics::init();
+
+    let config =
+        Config::load(Options::from_args()).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+    let address = config.address.clone();
+    let admin_token = config.admin_token.clone();
+    let rate_limits = config.rate_limits;
+    let intervals = config.intervals;
+    let bans =
+        bans::Bans::load(config.bans_file.clone()).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+
+    info!("Running on {}", address);
+    if admin_token.is_none() {
+        info!("No admin token given, the admin API is disabled");
+    }
     let system = System::new("agar-io");
-    let world_actor = world::World::default().start();
+    let rooms_actor = rooms::Rooms::from_config(config).start();
+    let bans_actor = bans.start();
 
     HttpServer::new(move || {
         App::new()
-            .data(world_actor.clone())
-            .route("/ws/", web::get().to(index))
+            .data(rooms_actor.clone())
+            .data(bans_actor.clone())
+            .data(rate_limits)
+            .data(intervals)
+            .data(admin::AdminToken(admin_token.clone()))
+            .route("/ws/", web::get().to_async(index))
+            .route("/ws/{room}", web::get().to_async(index))
+            .route("/healthz", web::get().to_async(alive))
+            .route("/readyz", web::get().to_async(ready))
+            .route("/metrics", web::get().to(metrics))
+            .configure(admin::configure)
     })
-    .bind("127.0.0.1:5555")?
+    .bind(address)?
     .start();
 
     system.run()
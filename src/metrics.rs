@@ -0,0 +1,86 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+    Encoder, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge, TextEncoder,
+};
+
+// Shared by every room, the actors update them as they go
+lazy_static! {
+    /// Players with an open connection to their world
+    pub static ref CONNECTED_PLAYERS: IntGauge =
+        register_int_gauge!("agar_connected_players", "Players connected to a world").unwrap();
+    /// Players kept by the players actors, including the disconnected ones still in their grace period
+    pub static ref PLAYERS: IntGauge = register_int_gauge!("agar_players", "Players in the players actors").unwrap();
+    pub static ref DOTS: IntGauge = register_int_gauge!("agar_dots", "Dots in the worlds").unwrap();
+    pub static ref MESSAGES_RECEIVED: IntCounterVec = register_int_counter_vec!(
+        "agar_messages_received_total",
+        "Messages received from the clients by type",
+        &["type"]
+    )
+    .unwrap();
+    /// Messages over the rate limit of their type. Moves are merged into the next one rather than refused
+    pub static ref RATE_LIMITED_MESSAGES: IntCounterVec = register_int_counter_vec!(
+        "agar_rate_limited_messages_total",
+        "Messages over the rate limit by type",
+        &["type"]
+    )
+    .unwrap();
+    /// Messages that couldn't be parsed or had an unknown type
+    pub static ref INVALID_MESSAGES: IntCounter =
+        register_int_counter!("agar_invalid_messages_total", "Invalid messages received from the clients").unwrap();
+    pub static ref SERIALIZATION_SECONDS: Histogram = register_histogram!(
+        "agar_serialization_seconds",
+        "Time spent serializing the messages sent to the clients",
+        vec![0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05]
+    )
+    .unwrap();
+    /// From the tick of a send interval until every player got its update
+    pub static ref BROADCAST_SECONDS: HistogramVec = register_histogram_vec!(
+        "agar_broadcast_duration_seconds",
+        "Time taken to send an interval update to every player",
+        &["interval"]
+    )
+    .unwrap();
+}
+
+/// Registers the metrics up front, so that they are exported before their first update
+pub fn init() {
+    lazy_static::initialize(&CONNECTED_PLAYERS);
+    lazy_static::initialize(&PLAYERS);
+    lazy_static::initialize(&DOTS);
+    lazy_static::initialize(&MESSAGES_RECEIVED);
+    lazy_static::initialize(&RATE_LIMITED_MESSAGES);
+    lazy_static::initialize(&INVALID_MESSAGES);
+    lazy_static::initialize(&SERIALIZATION_SECONDS);
+    lazy_static::initialize(&BROADCAST_SECONDS);
+}
+
+/// Every metric in the Prometheus text format
+pub fn render() -> String {
+    let mut buffer = Vec::new();
+
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .expect("Couldn't encode the metrics");
+
+    String::from_utf8(buffer).expect("Metrics aren't valid UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_render() {
+        MESSAGES_RECEIVED.with_label_values(&["move"]).inc();
+        BROADCAST_SECONDS.with_label_values(&["dots"]).observe(0.01);
+        INVALID_MESSAGES.inc();
+
+        let metrics = render();
+
+        assert!(metrics.contains("agar_messages_received_total{type=\"move\"}"));
+        assert!(metrics.contains("agar_broadcast_duration_seconds_count{interval=\"dots\"}"));
+        assert!(metrics.contains("agar_invalid_messages_total"));
+        assert!(metrics.contains("agar_connected_players"));
+    }
+}
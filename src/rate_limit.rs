@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::config::{RateLimit, RateLimits};
+
+/// Holds up to burst tokens, refilled at per_second tokens per second. Each message takes one
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Starts full, so that a client can send a burst right away
+    pub fn new(limit: RateLimit, now: Instant) -> Self {
+        TokenBucket {
+            limit,
+            tokens: f64::from(limit.burst),
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.limit.per_second).min(f64::from(self.limit.burst));
+        self.last_refill = now;
+    }
+
+    pub fn try_take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Time until the next token is available
+    pub fn wait_time(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+
+        Duration::from_secs_f64((1.0 - self.tokens).max(0.0) / self.limit.per_second)
+    }
+}
+
+/// A bucket per message type, and one for the messages refused that ends the connection once empty
+#[derive(Debug)]
+pub struct RateLimiter {
+    buckets: HashMap<&'static str, TokenBucket>,
+    violations: TokenBucket,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &RateLimits) -> Self {
+        let now = Instant::now();
+
+        RateLimiter {
+            buckets: limits
+                .by_type()
+                .iter()
+                .map(|(kind, limit)| (*kind, TokenBucket::new(*limit, now)))
+                .collect(),
+            violations: TokenBucket::new(limits.abuse, now),
+        }
+    }
+
+    /// Types without a limit are always let through
+    pub fn try_take(&mut self, kind: &str, now: Instant) -> bool {
+        self.buckets.get_mut(kind).is_none_or(|bucket| bucket.try_take(now))
+    }
+
+    pub fn wait_time(&mut self, kind: &str, now: Instant) -> Duration {
+        self.buckets
+            .get_mut(kind)
+            .map_or(Duration::from_secs(0), |bucket| bucket.wait_time(now))
+    }
+
+    /// Counts a refused or invalid message. False once the client went over the abuse limit
+    pub fn record_violation(&mut self, now: Instant) -> bool {
+        self.violations.try_take(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(
+            RateLimit {
+                per_second: 10.0,
+                burst: 3,
+            },
+            now,
+        );
+
+        assert!(bucket.try_take(now));
+        assert!(bucket.try_take(now));
+        assert!(bucket.try_take(now));
+        assert!(!bucket.try_take(now));
+        assert_eq!(bucket.wait_time(now), Duration::from_millis(100));
+
+        // A token every 100ms, but never more than the burst
+        assert!(!bucket.try_take(now + Duration::from_millis(50)));
+        assert!(bucket.try_take(now + Duration::from_millis(100)));
+        assert!(!bucket.try_take(now + Duration::from_millis(150)));
+
+        let later = now + Duration::from_secs(10);
+        assert!(bucket.try_take(later));
+        assert!(bucket.try_take(later));
+        assert!(bucket.try_take(later));
+        assert!(!bucket.try_take(later));
+    }
+
+    #[test]
+    fn test_rate_limiter_abuse() {
+        let now = Instant::now();
+        let limits = RateLimits {
+            abuse: RateLimit {
+                per_second: 1.0,
+                burst: 2,
+            },
+            ..RateLimits::default()
+        };
+        let mut limiter = RateLimiter::new(&limits);
+
+        assert!(limiter.try_take("split", now));
+        assert!(limiter.try_take("invalid", now));
+        assert!(limiter.record_violation(now));
+        assert!(limiter.record_violation(now));
+        assert!(!limiter.record_violation(now));
+    }
+}
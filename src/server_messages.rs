@@ -3,23 +3,199 @@ use serde::Serialize;
 use std::collections::HashMap;
 use uuid::Uuid;
 
+use crate::actors::dots::DotData;
+use crate::actors::players::{CellData, LeaderboardEntry, PlayerInViewport, Profile};
+use crate::actors::viruses::VirusData;
 use crate::actors::world::Coordinates;
 
+/// Every response carries its kind in a "type" field, e.g. {"type": "correction", ...}
 #[derive(Serialize)]
-#[serde(untagged)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum ServerResponses {
-    #[allow(dead_code)]
     Create(CreateResponse),
+    Dots(DotsResponse),
+    Players(PlayersResponse),
+    Viruses(VirusesResponse),
+    Correction(CorrectionResponse),
+    Eaten(EatenResponse),
+    Consumed(ConsumedResponse),
+    PlayerDied(PlayerDiedResponse),
+    PlayerLeft(PlayerLeftResponse),
+    Leaderboard(LeaderboardResponse),
+    ChatMessage(ChatMessageResponse),
+    Error(ErrorResponse),
 }
 
 #[derive(Message, Serialize, Debug)]
 pub struct CreateResponse {
+    pub protocol_version: u32,
     pub id: Uuid,
+    pub token: Uuid,
     pub world_size: Coordinates,
-    pub dots: HashMap<Uuid, Coordinates>,
+    pub dots: HashMap<Uuid, DotData>,
+    pub viruses: HashMap<Uuid, VirusData>,
 }
 
-#[derive(Serialize)]
+/// The changes in the dots of the viewport since the previous DotsResponse or CreateResponse
+#[derive(Message, Serialize, Debug)]
 pub struct DotsResponse {
-    pub dots:HashMap<Uuid, Coordinates>
+    pub dots_added: HashMap<Uuid, DotData>,
+    pub dots_removed: Vec<Uuid>,
+}
+
+#[derive(Serialize)]
+pub struct VirusesResponse {
+    pub viruses: HashMap<Uuid, VirusData>,
+}
+
+#[derive(Serialize)]
+pub struct PlayersResponse {
+    pub players: Vec<PlayerInViewport>,
+}
+
+/// The authoritative cells of the player, sent when they differ from what the client expects
+#[derive(Message, Serialize, Debug)]
+pub struct CorrectionResponse {
+    pub cells: Vec<CellData>,
+}
+
+/// Sent to a player that was eaten by another player
+#[derive(Message, Serialize, Debug)]
+pub struct EatenResponse {
+    pub by: Uuid,
+}
+
+/// Sent to a player that ate another player
+#[derive(Message, Serialize, Debug)]
+pub struct ConsumedResponse {
+    pub id: Uuid,
+    pub size: u32,
+}
+
+/// The final stats of a player, sent right after it died
+#[derive(Message, Serialize, Debug)]
+pub struct PlayerDiedResponse {
+    pub size: u32,
+    pub max_size: u32,
+    pub dots_eaten: u32,
+    pub players_eaten: u32,
+    // In seconds
+    pub time_alive: u64,
+}
+
+/// Sent to the players that had a disconnected player in their viewport
+#[derive(Message, Serialize, Debug)]
+pub struct PlayerLeftResponse {
+    pub id: Uuid,
+}
+
+/// The biggest players of the world, and the rank of the player. None while it is dead
+#[derive(Message, Serialize, Debug)]
+pub struct LeaderboardResponse {
+    pub leaders: Vec<LeaderboardEntry>,
+    pub rank: Option<u32>,
+}
+
+/// A chat message of a player. Also sent back to its sender
+#[derive(Message, Serialize, Debug, Clone)]
+pub struct ChatMessageResponse {
+    pub id: Uuid,
+    pub profile: Profile,
+    pub message: String,
+    pub proximity: bool,
+}
+
+/// Sent when a request of the client couldn't be handled
+#[derive(Message, Serialize, Debug)]
+pub struct ErrorResponse {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ErrorResponse {
+    pub fn new(code: ErrorCode, message: &str) -> Self {
+        ErrorResponse {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    // The message couldn't be parsed, or its type is unknown
+    Malformed,
+    // The request is about a player that isn't the one of the client
+    UnknownPlayer,
+    // The request needs a player, but the client didn't create one yet
+    NotCreatedYet,
+    // The client already created its player, or is creating it
+    AlreadyCreated,
+    // The message went over the rate limit of its type and was dropped
+    RateLimited,
+    // The nickname or the skin of the create request is invalid
+    InvalidProfile,
+    // The viewport size of the create or respawn request is out of MIN_VIEWPORT_SIZE..=MAX_VIEWPORT_SIZE
+    InvalidViewport,
+    // The chat message is empty or longer than MAX_CHAT_MESSAGE_LENGTH
+    InvalidChatMessage,
+    // Something went wrong on the server side
+    Internal,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_responses_tag() {
+        let by = Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").unwrap();
+        let eaten = serde_json::to_value(ServerResponses::Eaten(EatenResponse { by })).unwrap();
+        let died = serde_json::to_value(ServerResponses::PlayerDied(PlayerDiedResponse {
+            size: 20,
+            max_size: 40,
+            dots_eaten: 3,
+            players_eaten: 1,
+            time_alive: 60,
+        }))
+        .unwrap();
+
+        assert_eq!(eaten["type"], "eaten");
+        assert_eq!(eaten["by"], "f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4");
+        assert_eq!(died["type"], "player_died");
+        assert_eq!(died["max_size"], 40);
+    }
+
+    #[test]
+    fn test_server_responses_error() {
+        let error = serde_json::to_value(ServerResponses::Error(ErrorResponse::new(
+            ErrorCode::NotCreatedYet,
+            "Create a player first",
+        )))
+        .unwrap();
+
+        assert_eq!(error["type"], "error");
+        assert_eq!(error["code"], "not_created_yet");
+        assert_eq!(error["message"], "Create a player first");
+    }
+
+    #[test]
+    fn test_server_responses_message_pack() {
+        #[derive(serde::Deserialize)]
+        struct Tagged {
+            #[serde(rename = "type")]
+            kind: String,
+            by: Uuid,
+        }
+
+        let by = Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").unwrap();
+        let payload = rmp_serde::to_vec_named(&ServerResponses::Eaten(EatenResponse { by })).unwrap();
+        let eaten: Tagged = rmp_serde::from_slice(&payload).unwrap();
+
+        // The tag and the 16 bytes of the id, instead of the 36 characters of its JSON string
+        assert!(payload.len() < 40);
+        assert_eq!(eaten.kind, "eaten");
+        assert_eq!(eaten.by, by);
+    }
 }
@@ -2,43 +2,44 @@
 use rand::Rng;
 use uuid::Uuid;
 
+use std::cmp;
 use std::collections::HashMap;
 
+use crate::actors::dots::Dot;
 use crate::actors::world::Coordinates;
 
 #[cfg(not(test))]
-use crate::consts::{WORLD_X_SIZE, WORLD_Y_SIZE};
-
-#[cfg(not(test))]
-pub fn generate_coordinates() -> Coordinates {
+pub fn generate_coordinates(world_size: Coordinates) -> Coordinates {
     let mut generator = rand::thread_rng();
-    let x: u32 = generator.gen_range(0, WORLD_X_SIZE);
-    let y: u32 = generator.gen_range(0, WORLD_Y_SIZE);
+    let x: u32 = generator.gen_range(0, world_size.x);
+    let y: u32 = generator.gen_range(0, world_size.y);
 
     Coordinates { x, y }
 }
 
 #[cfg(test)]
-pub fn generate_coordinates() -> Coordinates {
+pub fn generate_coordinates(_world_size: Coordinates) -> Coordinates {
     Coordinates { x: 100, y: 100 }
 }
 
-#[cfg(not(test))]
-pub fn generate_dots(dots: &mut HashMap<Uuid, Coordinates>, max_count: u32) {
-    let new_dots_count = max_count - dots.len() as u32;
+// Moves a position by a signed delta, saturating at 0 and max. Also tells whether the position was clamped
+pub fn apply_delta(position: u32, delta: i32, max: u32) -> (u32, bool) {
+    let target = i64::from(position) + i64::from(delta);
+    let clamped = cmp::min(cmp::max(target, 0), i64::from(max));
 
-    let new_dots: HashMap<Uuid, Coordinates> = (0..new_dots_count)
-        .map(|_| (Uuid::new_v4(), generate_coordinates()))
-        .collect();
+    (clamped as u32, clamped != target)
+}
 
-    for (dot_id, dot_coordinates) in new_dots {
-        dots.insert(dot_id, dot_coordinates);
-    }
+#[cfg(not(test))]
+pub fn generate_dots(count: u32, world_size: Coordinates) -> HashMap<Uuid, Dot> {
+    (0..count)
+        .map(|_| (Uuid::new_v4(), Dot::new(generate_coordinates(world_size))))
+        .collect()
 }
 
 #[cfg(test)]
-pub fn generate_dots(dots: &mut HashMap<Uuid, Coordinates>, _max_count: u32) {
-    let new_dots: HashMap<Uuid, Coordinates> = vec![
+pub fn generate_dots(_count: u32, _world_size: Coordinates) -> HashMap<Uuid, Dot> {
+    vec![
         (
             Uuid::parse_str("f9168c5e-ceb2-4faa-b6bf-329bf39fa1e4").unwrap(),
             Coordinates { x: 0, y: 0 },
@@ -89,9 +90,6 @@ pub fn generate_dots(dots: &mut HashMap<Uuid, Coordinates>, _max_count: u32) {
         ),
     ]
     .into_iter()
-    .collect();
-
-    for (dot_id, dot_coordinates) in new_dots {
-        dots.insert(dot_id, dot_coordinates);
-    }
+    .map(|(dot_id, dot_coordinates)| (dot_id, Dot::new(dot_coordinates)))
+    .collect()
 }